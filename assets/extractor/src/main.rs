@@ -1,9 +1,194 @@
-use std::{collections::HashMap, env};
+use std::{
+    collections::HashMap,
+    env, fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Version of protocol between the library and the extractor. Version 1 is a bare
+/// JSON object with environment variables.
+const PROTOCOL: u32 = 2;
+
+/// Prefix of the line with the envelope. Everything else in stdout is printed by
+/// startup files of the shell.
+const MARKER: &str = "ENVVARS_ENVELOPE|";
+
+/// If this variable is defined, the extractor reports the context of the process
+/// in addition to environment variables
+const CONTEXT_VAR: &str = "ENVVARS_CONTEXT";
+
+/// Version of protocol requested by the library. If not defined, the extractor
+/// prints a bare JSON object (protocol 1)
+const PROTOCOL_VAR: &str = "ENVVARS_PROTOCOL";
+
+/// Random string of the library, which is returned in the envelope to confirm
+/// the envelope is printed by the extractor
+const NONCE_VAR: &str = "ENVVARS_NONCE";
+
+/// Signature of the extractor. The library looks for it to recognize extractors of
+/// other builds, which can be removed.
+const MAGIC: &str = "ENVVARS_EXTRACTOR_MAGIC|1";
+
+/// Categories of locale in order of `LC_ALL` > `LC_*` > `LANG`
+const LOCALE_CATEGORIES: [&str; 6] = [
+    "LC_CTYPE",
+    "LC_NUMERIC",
+    "LC_TIME",
+    "LC_COLLATE",
+    "LC_MONETARY",
+    "LC_MESSAGES",
+];
+
+#[cfg(unix)]
+fn umask() -> Option<u32> {
+    // umask cannot be read without setting it, the value is restored right away
+    let mask = unsafe { libc::umask(0o022) };
+    unsafe { libc::umask(mask) };
+    Some(mask as u32)
+}
+
+#[cfg(not(unix))]
+fn umask() -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn rlimits() -> serde_json::Map<String, serde_json::Value> {
+    let limit = |value: libc::rlim_t| {
+        if value == libc::RLIM_INFINITY {
+            serde_json::Value::Null
+        } else {
+            serde_json::Value::from(value)
+        }
+    };
+    let mut rlimits = serde_json::Map::new();
+    for (name, resource) in [
+        ("core", libc::RLIMIT_CORE),
+        ("cpu", libc::RLIMIT_CPU),
+        ("data", libc::RLIMIT_DATA),
+        ("fsize", libc::RLIMIT_FSIZE),
+        ("memlock", libc::RLIMIT_MEMLOCK),
+        ("nofile", libc::RLIMIT_NOFILE),
+        ("nproc", libc::RLIMIT_NPROC),
+        ("stack", libc::RLIMIT_STACK),
+        ("as", libc::RLIMIT_AS),
+    ] {
+        let mut rlimit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(resource, &mut rlimit) } == 0 {
+            rlimits.insert(
+                name.to_owned(),
+                serde_json::json!({ "soft": limit(rlimit.rlim_cur), "hard": limit(rlimit.rlim_max) }),
+            );
+        }
+    }
+    rlimits
+}
+
+#[cfg(not(unix))]
+fn rlimits() -> serde_json::Map<String, serde_json::Value> {
+    serde_json::Map::new()
+}
+
+/// Returns locale of each category. On unix the locale is applied, so a locale,
+/// which isn't installed, is reported as "C".
+#[cfg(unix)]
+fn locale() -> HashMap<String, String> {
+    use std::ffi::CStr;
+    let mut locale: HashMap<String, String> = HashMap::new();
+    unsafe { libc::setlocale(libc::LC_ALL, c"".as_ptr()) };
+    for (name, category) in LOCALE_CATEGORIES.iter().zip([
+        libc::LC_CTYPE,
+        libc::LC_NUMERIC,
+        libc::LC_TIME,
+        libc::LC_COLLATE,
+        libc::LC_MONETARY,
+        libc::LC_MESSAGES,
+    ]) {
+        let value = unsafe { libc::setlocale(category, std::ptr::null()) };
+        if !value.is_null() {
+            let value = unsafe { CStr::from_ptr(value) };
+            locale.insert(name.to_string(), value.to_string_lossy().to_string());
+        }
+    }
+    locale
+}
+
+#[cfg(not(unix))]
+fn locale() -> HashMap<String, String> {
+    let var = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+    LOCALE_CATEGORIES
+        .iter()
+        .filter_map(|name| {
+            var("LC_ALL")
+                .or(var(name))
+                .or(var("LANG"))
+                .map(|value| (name.to_string(), value))
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn ppid() -> Option<u32> {
+    Some(std::os::unix::process::parent_id())
+}
+
+#[cfg(not(unix))]
+fn ppid() -> Option<u32> {
+    None
+}
+
+/// Returns checksum of the executable file of the extractor. On Linux the file is
+/// read through `/proc/self/exe`, which works also if the extractor is loaded into
+/// memory and doesn't have a path.
+fn checksum() -> Option<String> {
+    let path = if cfg!(target_os = "linux") {
+        std::path::PathBuf::from("/proc/self/exe")
+    } else {
+        env::current_exe().ok()?
+    };
+    let bytes = fs::read(path).ok()?;
+    Some(blake3::hash(&bytes).to_string())
+}
 
 pub fn main() {
+    // Keeps the signature in the executable file
+    std::hint::black_box(MAGIC);
     let mut envvars: HashMap<String, String> = HashMap::new();
     for (key, value) in env::vars() {
         envvars.insert(key, value);
     }
-    println!("{}", serde_json::to_string(&envvars).unwrap());
+    let context = envvars.remove(CONTEXT_VAR).is_some();
+    let nonce = envvars.remove(NONCE_VAR);
+    let requested = envvars
+        .remove(PROTOCOL_VAR)
+        .and_then(|v| v.parse::<u32>().ok());
+    let Some(requested) = requested.filter(|v| *v >= 2) else {
+        println!("{}", serde_json::to_string(&envvars).unwrap());
+        return;
+    };
+    let context = if context {
+        serde_json::json!({
+            "cwd": env::current_dir().ok(),
+            "umask": umask(),
+            "rlimits": rlimits(),
+            "locale": locale(),
+        })
+    } else {
+        serde_json::Value::Null
+    };
+    let envelope = serde_json::json!({
+        "protocol": requested.min(PROTOCOL),
+        "checksum": checksum(),
+        "pid": std::process::id(),
+        "ppid": ppid(),
+        "timestamp": SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default(),
+        "nonce": nonce,
+        "payload": { "envvars": envvars, "context": context },
+    });
+    println!("\n{MARKER}{envelope}");
 }
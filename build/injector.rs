@@ -23,7 +23,7 @@ pub fn inject() -> Result<(), Error> {
             "
 static BIN: &[u8] = &{buffer:?};
 static CHECKSUM: &str = \"{}\";
-static FILENAME: &str = \"{}\";
+static FILENAME: &str = \"envvars-extractor-{}\";
         ",
             checksum(&paths::extractor_executable()?)?,
             Uuid::new_v4(),
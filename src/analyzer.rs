@@ -0,0 +1,570 @@
+use crate::{
+    merge::{current, is_path_list},
+    profiles::startup,
+    syntax::{is_name, statements, words},
+    Error, Mode, ShellKind,
+};
+use home::home_dir;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Max depth of `source` chains
+const MAX_DEPTH: usize = 8;
+
+/// Value of variable detected without execution of startup files
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub enum StaticValue {
+    /// Value is fully resolved
+    Resolved(String),
+    /// Value cannot be resolved without execution: it has command substitution,
+    /// refers to unknown variable or is assigned conditionally. Contains the
+    /// expression as it's written in the startup file.
+    Unresolved(String),
+}
+
+/// Exported variable detected without execution of startup files
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct StaticVar {
+    /// Value of variable
+    pub value: StaticValue,
+    /// Startup file, which has the last assignment
+    pub file: PathBuf,
+    /// Line in startup file
+    pub line: usize,
+}
+
+/// Splits a word like `NAME=value` into name and raw value. Quoted assignments like
+/// `'NAME=value'` are converted into `NAME='value'`.
+fn assignment(word: &str) -> Option<(String, String)> {
+    let (name, raw) = word.split_once('=')?;
+    if is_name(name) {
+        return Some((name.to_owned(), raw.to_owned()));
+    }
+    let quote = name.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+    let name = &name[1..];
+    is_name(name).then(|| (name.to_owned(), format!("{quote}{raw}")))
+}
+
+struct Analyzer {
+    kind: ShellKind,
+    base: HashMap<String, String>,
+    vars: HashMap<String, StaticVar>,
+    exported: HashSet<String>,
+    visited: HashSet<PathBuf>,
+}
+
+impl Analyzer {
+    fn lookup(&self, name: &str) -> Option<String> {
+        match self.vars.get(name) {
+            Some(var) => match &var.value {
+                StaticValue::Resolved(value) => Some(value.clone()),
+                StaticValue::Unresolved(_) => None,
+            },
+            None => self.base.get(name).cloned(),
+        }
+    }
+
+    /// Expands a word with quotes. Returns `None` if the word cannot be resolved
+    /// without execution.
+    fn expand(&self, raw: &str) -> Option<String> {
+        let mut result = String::new();
+        let mut chars = raw.chars().peekable();
+        let mut quoted = false;
+        if raw.starts_with('~') {
+            chars.next();
+            if matches!(chars.peek(), None | Some('/')) {
+                result.push_str(&home_dir()?.to_string_lossy());
+            } else {
+                return None;
+            }
+        }
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' if !quoted => {
+                    for c in chars.by_ref() {
+                        if c == '\'' {
+                            break;
+                        }
+                        result.push(c);
+                    }
+                }
+                '"' => quoted = !quoted,
+                '\\' => result.push(chars.next()?),
+                '`' => return None,
+                '$' => match chars.peek() {
+                    Some('{') => {
+                        chars.next();
+                        let expr = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                        result.push_str(&self.expand_expr(&expr)?);
+                    }
+                    Some(c) if c.is_ascii_alphabetic() || *c == '_' => {
+                        let mut name = String::new();
+                        while let Some(&c) = chars.peek() {
+                            if c.is_ascii_alphanumeric() || c == '_' {
+                                name.push(c);
+                                chars.next();
+                            } else {
+                                break;
+                            }
+                        }
+                        result.push_str(&self.lookup(&name)?);
+                    }
+                    // Command substitutions, positional and special parameters
+                    _ => return None,
+                },
+                _ => result.push(c),
+            }
+        }
+        Some(result)
+    }
+
+    /// Expands `${...}` expression: `${NAME}`, `${NAME:-default}`, `${NAME-default}`
+    fn expand_expr(&self, expr: &str) -> Option<String> {
+        if is_name(expr) {
+            return self.lookup(expr);
+        }
+        let (name, default, empty) = if let Some((name, default)) = expr.split_once(":-") {
+            (name, default, true)
+        } else {
+            let (name, default) = expr.split_once('-')?;
+            (name, default, false)
+        };
+        if !is_name(name) {
+            return None;
+        }
+        match self.lookup(name) {
+            Some(value) if !(empty && value.is_empty()) => Some(value),
+            Some(_) | None if self.vars.contains_key(name) => None,
+            _ => self.expand(default),
+        }
+    }
+
+    fn assign(&mut self, name: &str, raw: &str, conditional: bool, file: &Path, line: usize) {
+        let value = match self.expand(raw) {
+            Some(value) if !conditional => StaticValue::Resolved(value),
+            _ => StaticValue::Unresolved(raw.to_owned()),
+        };
+        self.vars.insert(
+            name.to_owned(),
+            StaticVar {
+                value,
+                file: file.to_path_buf(),
+                line,
+            },
+        );
+    }
+
+    fn unset(&mut self, name: &str, conditional: bool, file: &Path, line: usize) {
+        if conditional {
+            self.assign(name, "", true, file, line);
+        } else {
+            self.vars.remove(name);
+            self.base.remove(name);
+            self.exported.remove(name);
+        }
+    }
+
+    /// Analyzes the sourced file. If `source` is conditional, everything found in
+    /// the file is conditional as well.
+    fn source(&mut self, raw: &str, conditional: bool, depth: usize) {
+        let Some(path) = self.expand(raw).map(PathBuf::from) else {
+            return;
+        };
+        if path.is_file() {
+            self.file(&path, conditional, depth + 1);
+        }
+    }
+
+    fn posix(
+        &mut self,
+        statement: &[String],
+        conditional: bool,
+        depth: usize,
+        file: &Path,
+        line: usize,
+    ) {
+        let command = words(&statement[0]).join(" ");
+        let words = statement;
+        match command.as_str() {
+            "export" | "declare" | "typeset" | "readonly" => {
+                let flags = words
+                    .iter()
+                    .skip(1)
+                    .take_while(|w| w.starts_with('-'))
+                    .collect::<Vec<&String>>();
+                let export = command == "export" || flags.iter().any(|f| f.contains('x'));
+                for word in words.iter().skip(1 + flags.len()) {
+                    let (name, raw) = match assignment(word) {
+                        Some((name, raw)) => (name, Some(raw)),
+                        None => (word.to_owned(), None),
+                    };
+                    if !is_name(&name) {
+                        continue;
+                    }
+                    if export {
+                        self.exported.insert(name.clone());
+                    }
+                    if let Some(raw) = raw {
+                        self.assign(&name, &raw, conditional, file, line);
+                    }
+                }
+            }
+            "unset" => {
+                for name in words.iter().skip(1).filter(|w| is_name(w)) {
+                    self.unset(name, conditional, file, line);
+                }
+            }
+            "source" | "." => {
+                if let Some(raw) = words.get(1) {
+                    self.source(raw, conditional, depth);
+                }
+            }
+            _ => {
+                // Plain assignments; if there is a command after, assignments are
+                // done only for the command
+                let assignments = words
+                    .iter()
+                    .map(|w| assignment(w))
+                    .collect::<Option<Vec<(String, String)>>>();
+                for (name, raw) in assignments.unwrap_or_default() {
+                    self.assign(&name, &raw, conditional, file, line);
+                }
+            }
+        }
+    }
+
+    fn fish(
+        &mut self,
+        words: &[String],
+        conditional: bool,
+        depth: usize,
+        file: &Path,
+        line: usize,
+    ) {
+        match words[0].as_str() {
+            "set" => {
+                let flags = words
+                    .iter()
+                    .skip(1)
+                    .take_while(|w| w.starts_with('-'))
+                    .collect::<Vec<&String>>();
+                let has = |short: char, long: &str| {
+                    flags
+                        .iter()
+                        .any(|f| f.as_str() == long || (!f.starts_with("--") && f.contains(short)))
+                };
+                let Some(name) = words.get(1 + flags.len()).filter(|n| is_name(n)) else {
+                    return;
+                };
+                if has('e', "--erase") {
+                    self.unset(name, conditional, file, line);
+                    return;
+                }
+                if has('q', "--query") || has('n', "--names") || has('S', "--show") {
+                    return;
+                }
+                if has('x', "--export") {
+                    self.exported.insert(name.clone());
+                }
+                let separator = if is_path_list(name) { ":" } else { " " };
+                let raw = words[2 + flags.len()..].join(separator);
+                self.assign(name, &raw, conditional, file, line);
+            }
+            "fish_add_path" => {
+                let paths = words
+                    .iter()
+                    .skip(1)
+                    .filter(|w| !w.starts_with('-'))
+                    .cloned()
+                    .collect::<Vec<String>>();
+                if !paths.is_empty() {
+                    let raw = format!("{}:$PATH", paths.join(":"));
+                    self.assign("PATH", &raw, conditional, file, line);
+                }
+            }
+            "source" => {
+                if let Some(raw) = words.get(1) {
+                    self.source(raw, conditional, depth);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn csh(&mut self, words: &[String], conditional: bool, depth: usize, file: &Path, line: usize) {
+        match words[0].as_str() {
+            "setenv" => {
+                if let Some(name) = words.get(1).filter(|n| is_name(n)) {
+                    self.exported.insert(name.clone());
+                    let raw = words.get(2).cloned().unwrap_or_default();
+                    self.assign(name, &raw, conditional, file, line);
+                }
+            }
+            "unsetenv" => {
+                if let Some(name) = words.get(1) {
+                    self.unset(name, conditional, file, line);
+                }
+            }
+            "source" => {
+                if let Some(raw) = words.get(1) {
+                    self.source(raw, conditional, depth);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Analyzes a startup file. Each line is considered as separate statements;
+    /// everything inside of conditions, loops and functions, statements chained
+    /// with `&&` or `||` and everything in a file sourced conditionally (`sourced`)
+    /// are considered as conditional and produce unresolved values.
+    fn file(&mut self, path: &Path, sourced: bool, depth: usize) {
+        if depth > MAX_DEPTH || !self.visited.insert(path.to_path_buf()) {
+            return;
+        }
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => {
+                log::warn!("Fail to read {path:?}: {err}");
+                return;
+            }
+        };
+        let mut nested: usize = 0;
+        for (n, line) in content.lines().enumerate() {
+            for (statement, chained) in statements(line) {
+                let first = words(&statement[0]).join(" ");
+                let (opens, closes) = match self.kind {
+                    ShellKind::Fish => (
+                        matches!(
+                            first.as_str(),
+                            "if" | "for" | "while" | "function" | "switch" | "begin"
+                        ),
+                        first == "end",
+                    ),
+                    ShellKind::Csh => (
+                        matches!(first.as_str(), "if" | "foreach" | "while" | "switch")
+                            && statement.last().map(|w| w != "endif").unwrap_or(true)
+                            && (first != "if" || statement.iter().any(|w| w == "then")),
+                        matches!(first.as_str(), "endif" | "end" | "endsw"),
+                    ),
+                    _ => (
+                        matches!(first.as_str(), "if" | "case" | "for" | "while" | "until")
+                            || statement.last().map(|w| w == "{").unwrap_or(false),
+                        matches!(first.as_str(), "fi" | "esac" | "done" | "}"),
+                    ),
+                };
+                if opens {
+                    nested += 1;
+                }
+                let conditional = sourced || chained || nested > 0;
+                let statement = if opens && self.kind != ShellKind::Fish {
+                    // "if [ ... ]; then A=1; fi" has statements after keywords
+                    statement
+                        .into_iter()
+                        .skip_while(|w| !matches!(w.as_str(), "then" | "do"))
+                        .skip(1)
+                        .collect::<Vec<String>>()
+                } else {
+                    statement
+                };
+                if closes {
+                    nested = nested.saturating_sub(1);
+                    continue;
+                }
+                if statement.is_empty() {
+                    continue;
+                }
+                let statement = match statement[0].as_str() {
+                    "then" | "do" | "else" => statement[1..].to_vec(),
+                    _ => statement,
+                };
+                if statement.is_empty() {
+                    continue;
+                }
+                match self.kind {
+                    ShellKind::Fish => self.fish(&statement, conditional, depth, path, n + 1),
+                    ShellKind::Csh => self.csh(&statement, conditional, depth, path, n + 1),
+                    _ => self.posix(&statement, conditional, depth, path, n + 1),
+                }
+            }
+        }
+    }
+}
+
+/// Walks startup files of the shell (and files sourced by them) without execution
+fn walk(kind: ShellKind, mode: Mode) -> Result<Analyzer, Error> {
+    if matches!(
+        kind,
+        ShellKind::Nu | ShellKind::PowerShell | ShellKind::Cmd | ShellKind::Unknown
+    ) {
+        return Err(Error::NotSupportedShell(format!("{kind:?}")));
+    }
+    let base = current();
+    let mut analyzer = Analyzer {
+        kind,
+        exported: base.keys().cloned().collect(),
+        base,
+        vars: HashMap::new(),
+        visited: HashSet::new(),
+    };
+    for file in startup::files(kind, mode).into_iter().filter(|f| f.read) {
+        analyzer.file(&file.path, false, 0);
+    }
+    Ok(analyzer)
+}
+
+/// Detects exported variables of startup files without execution. See
+/// `Profile::load_static`.
+pub(crate) fn analyze(kind: ShellKind, mode: Mode) -> Result<HashMap<String, StaticVar>, Error> {
+    let analyzer = walk(kind, mode)?;
+    let exported = analyzer.exported;
+    Ok(analyzer
+        .vars
+        .into_iter()
+        .filter(|(name, _)| exported.contains(name))
+        .collect())
+}
+
+/// Files read by the shell in the given mode: startup files and files sourced by
+/// them, if paths of sourced files can be resolved without execution. Sorted;
+/// empty for shells, which aren't supported by the analyzer.
+pub(crate) fn sourced(kind: ShellKind, mode: Mode) -> Vec<PathBuf> {
+    let mut files = walk(kind, mode)
+        .map(|analyzer| analyzer.visited.into_iter().collect::<Vec<PathBuf>>())
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// Analyzes the script written into a unique folder. `files` are written next
+    /// to the script; `{dir}` in the script is replaced with the folder.
+    fn analyze(name: &str, script: &str, files: &[(&str, &str)]) -> Analyzer {
+        let temp = TempDir::new(&format!("analyzer-{name}"));
+        let dir = temp.path();
+        for (file, content) in files {
+            fs::write(dir.join(file), content).expect("File should be written");
+        }
+        let main = dir.join("main.sh");
+        fs::write(&main, script.replace("{dir}", &dir.to_string_lossy()))
+            .expect("File should be written");
+        let mut analyzer = Analyzer {
+            kind: ShellKind::Bash,
+            base: HashMap::new(),
+            vars: HashMap::new(),
+            exported: HashSet::new(),
+            visited: HashSet::new(),
+        };
+        analyzer.file(&main, false, 0);
+        analyzer
+    }
+
+    fn value(analyzer: &Analyzer, name: &str) -> Option<StaticValue> {
+        analyzer.vars.get(name).map(|v| v.value.clone())
+    }
+
+    #[test]
+    fn assignments() {
+        let analyzer = analyze(
+            "assignments",
+            "BASE=/opt; export BASE\n\
+             export 'QUOTED=a b' LOCAL_ONLY\n\
+             NOT_EXPORTED=1\n",
+            &[],
+        );
+        assert_eq!(
+            value(&analyzer, "BASE"),
+            Some(StaticValue::Resolved("/opt".into()))
+        );
+        assert_eq!(
+            value(&analyzer, "QUOTED"),
+            Some(StaticValue::Resolved("a b".into()))
+        );
+        assert!(analyzer.exported.contains("BASE"));
+        assert!(analyzer.exported.contains("LOCAL_ONLY"));
+        assert!(!analyzer.exported.contains("NOT_EXPORTED"));
+    }
+
+    #[test]
+    fn command_substitution() {
+        let analyzer = analyze("command", "export FROM_CMD=$(uname)\n", &[]);
+        assert_eq!(
+            value(&analyzer, "FROM_CMD"),
+            Some(StaticValue::Unresolved("$(uname)".into()))
+        );
+    }
+
+    #[test]
+    fn default_expansion() {
+        let analyzer = analyze("default", "export DEFAULT=${UNKNOWN_VAR:-fallback}\n", &[]);
+        assert_eq!(
+            value(&analyzer, "DEFAULT"),
+            Some(StaticValue::Resolved("fallback".into()))
+        );
+    }
+
+    #[test]
+    fn conditional() {
+        let analyzer = analyze(
+            "conditional",
+            "if [ -d /opt ]; then\n\texport CONDITIONAL=1\nfi\n",
+            &[],
+        );
+        assert_eq!(
+            value(&analyzer, "CONDITIONAL"),
+            Some(StaticValue::Unresolved("1".into()))
+        );
+    }
+
+    #[test]
+    fn sourcing() {
+        let analyzer = analyze(
+            "sourcing",
+            "BASE=/opt\n. {dir}/included.sh\n",
+            &[("included.sh", "export INCLUDED=\"$BASE/inc\"\n")],
+        );
+        assert_eq!(
+            value(&analyzer, "INCLUDED"),
+            Some(StaticValue::Resolved("/opt/inc".into()))
+        );
+        assert_eq!(analyzer.vars.get("INCLUDED").map(|v| v.line), Some(1));
+        // Sourced files are tracked by the cache (see `sourced`)
+        assert!(analyzer.visited.iter().any(|p| p.ends_with("included.sh")));
+    }
+
+    #[test]
+    fn conditional_sourcing() {
+        let included = [("included.sh", "export INCLUDED=1\n")];
+        let analyzer = analyze(
+            "chained-sourcing",
+            "[ -f {dir}/included.sh ] && . {dir}/included.sh\n",
+            &included,
+        );
+        assert_eq!(
+            value(&analyzer, "INCLUDED"),
+            Some(StaticValue::Unresolved("1".into()))
+        );
+        let analyzer = analyze(
+            "if-sourcing",
+            "if [ -f {dir}/included.sh ]; then\n\tsource {dir}/included.sh\nfi\n",
+            &included,
+        );
+        assert_eq!(
+            value(&analyzer, "INCLUDED"),
+            Some(StaticValue::Unresolved("1".into()))
+        );
+        let analyzer = analyze("chained", "test -d /opt || export FALLBACK=1\n", &[]);
+        assert_eq!(
+            value(&analyzer, "FALLBACK"),
+            Some(StaticValue::Unresolved("1".into()))
+        );
+    }
+}
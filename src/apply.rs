@@ -0,0 +1,341 @@
+use crate::{
+    merge::{current, lookup, merge, MergeStrategy},
+    Error, Profile,
+};
+use serde::Serialize;
+use std::{collections::HashMap, env, sync::Mutex};
+
+/// Variables, which describe a state of a particular shell's instance and should
+/// not be moved into another process
+const DEFAULT_DENY: &[&str] = &["PWD", "OLDPWD", "SHLVL", "_"];
+
+lazy_static! {
+    #[doc(hidden)]
+    static ref APPLYING: Mutex<()> = Mutex::new(());
+}
+
+/// Defines which variables and how should be applied to the current process
+#[derive(Debug, Clone)]
+pub struct ApplyStrategy {
+    /// Defines how variables of the profile are combined with current environment
+    pub merge: MergeStrategy,
+    /// If defined, only listed variables will be touched
+    pub allow: Option<Vec<String>>,
+    /// Listed variables will never be touched. By default: `PWD`, `OLDPWD`,
+    /// `SHLVL` and `_`
+    pub deny: Vec<String>,
+}
+
+impl ApplyStrategy {
+    /// Creates strategy with the default list of denied variables
+    /// * `merge` - defines how variables of the profile are combined with current
+    ///   environment
+    pub fn new(merge: MergeStrategy) -> Self {
+        ApplyStrategy {
+            merge,
+            allow: None,
+            deny: DEFAULT_DENY.iter().map(|k| k.to_string()).collect(),
+        }
+    }
+
+    fn is_allowed(&self, key: &str) -> bool {
+        let same = |k: &String| {
+            if cfg!(windows) {
+                k.eq_ignore_ascii_case(key)
+            } else {
+                k == key
+            }
+        };
+        self.allow
+            .as_ref()
+            .map(|allow| allow.iter().any(same))
+            .unwrap_or(true)
+            && !self.deny.iter().any(same)
+    }
+}
+
+impl Default for ApplyStrategy {
+    fn default() -> Self {
+        Self::new(MergeStrategy::OverlayPaths)
+    }
+}
+
+/// Report of changes, which were applied to the current process
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApplyReport {
+    /// Variables, which didn't exist before
+    pub added: HashMap<String, String>,
+    /// Variables, which were changed: (previous value, new value)
+    pub changed: HashMap<String, (String, String)>,
+    /// Variables, which were removed with their previous values
+    pub removed: HashMap<String, String>,
+    /// Variables, which should be changed, but were skipped because of allowlist
+    /// or denylist, or because they cannot be changed by `std::env`: the name is
+    /// empty or has `=` or NUL (like hidden `=C:` variables of Windows), the value
+    /// has NUL
+    pub skipped: Vec<String>,
+}
+
+impl ApplyReport {
+    /// Returns true if nothing has been changed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Checks the variable can be changed with `std::env::set_var` and
+/// `std::env::remove_var` (both panic otherwise)
+fn is_settable(key: &str, value: &str) -> bool {
+    !key.is_empty() && !key.contains(['=', '\0']) && !value.contains('\0')
+}
+
+fn plan(
+    current: &HashMap<String, String>,
+    profile: &HashMap<String, String>,
+    strategy: &ApplyStrategy,
+) -> ApplyReport {
+    let target = merge(current, profile, strategy.merge);
+    let mut report = ApplyReport::default();
+    // Names are compared case-insensitively on windows (like in `merge`): `Path` of
+    // the profile and `PATH` of the process are the same variable
+    for (key, value) in target.iter() {
+        match lookup(current, key) {
+            Some(prev) if prev == value => continue,
+            _ if !strategy.is_allowed(key) || !is_settable(key, value) => {
+                report.skipped.push(key.clone())
+            }
+            Some(prev) => {
+                report
+                    .changed
+                    .insert(key.clone(), (prev.clone(), value.clone()));
+            }
+            None => {
+                report.added.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    for (key, value) in current.iter() {
+        if lookup(&target, key).is_some() {
+            continue;
+        }
+        if strategy.is_allowed(key) && is_settable(key, "") {
+            report.removed.insert(key.clone(), value.clone());
+        } else {
+            report.skipped.push(key.clone());
+        }
+    }
+    report.skipped.sort();
+    report
+}
+
+/// Applies environment variables of the profile to the current process and returns
+/// a report of changes. Only a difference with `std::env::vars` is applied: variables,
+/// which have the same values, aren't touched. Variables with non-unicode values are
+/// ignored. Exported functions of bash (see `Profile::functions`) are applied as
+/// `BASH_FUNC_name%%` variables.
+///
+/// Profile should be loaded before (see `Profile::load`), otherwise
+/// `Error::NotLoaded` is returned. Variables, which cannot be changed by `std::env`
+/// (see `ApplyReport::skipped`), are skipped.
+///
+/// # Thread safety
+///
+/// Modifying an environment of the process isn't thread-safe on most platforms: while
+/// `std::env::set_var` is called, any other thread, which reads environment (including
+/// reading by libc, for example `getaddrinfo` or `localtime`), could observe a broken
+/// state. `envvars` serializes own calls of `apply_to_current_process`, but cannot
+/// guard reading of environment from other places. That's why this function should be
+/// called once at startup of the application, before any other threads are spawned.
+///
+/// # Examples
+///
+/// ```
+/// use std::{path::PathBuf, str::FromStr};
+/// use envvars::{apply_to_current_process, ApplyStrategy, MergeStrategy, Profile};
+///
+/// let mut profile: Profile = if cfg!(windows) {
+///     Profile::new(&PathBuf::from_str("C:\\Program Files\\Git\\bin\\bash.exe").unwrap(), vec!["-c"], None).unwrap()
+/// } else {
+///     Profile::new(&PathBuf::from_str("/bin/bash").unwrap(), vec!["-c"], None).unwrap()
+/// };
+///
+/// profile.load().unwrap();
+///
+/// let mut strategy = ApplyStrategy::new(MergeStrategy::OverlayPaths);
+/// strategy.allow = Some(vec![String::from("PATH")]);
+/// let report = apply_to_current_process(&profile, strategy).unwrap();
+///
+/// assert!(report.added.keys().chain(report.changed.keys()).all(|k| k == "PATH"));
+/// ```
+pub fn apply_to_current_process(
+    profile: &Profile,
+    strategy: ApplyStrategy,
+) -> Result<ApplyReport, Error> {
+    // Exported functions of bash are applied as well
+    let envvars = profile
+        .raw_envvars()
+        .ok_or_else(|| Error::NotLoaded(profile.path.clone()))?;
+    // The lock guards nothing but the order of changes, so it's used as usual
+    // even if a thread panicked while holding it
+    let _guard = APPLYING.lock().unwrap_or_else(|poisoned| {
+        APPLYING.clear_poison();
+        poisoned.into_inner()
+    });
+    let report = plan(&current(), &envvars, &strategy);
+    for key in report.removed.keys() {
+        env::remove_var(key);
+    }
+    for (key, (_, value)) in report.changed.iter() {
+        env::set_var(key, value);
+    }
+    for (key, value) in report.added.iter() {
+        env::set_var(key, value);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn vars(list: &[(&str, &str)]) -> HashMap<String, String> {
+        list.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test() {
+        let current = vars(&[("KEEP", "1"), ("CHANGE", "1"), ("DROP", "1"), ("PWD", "/")]);
+        let profile = vars(&[
+            ("KEEP", "1"),
+            ("CHANGE", "2"),
+            ("ADD", "1"),
+            ("PWD", "/home"),
+        ]);
+        let report = plan(
+            &current,
+            &profile,
+            &ApplyStrategy::new(MergeStrategy::Replace),
+        );
+        assert_eq!(report.added, vars(&[("ADD", "1")]));
+        assert_eq!(
+            report.changed,
+            HashMap::from([(
+                String::from("CHANGE"),
+                (String::from("1"), String::from("2"))
+            )])
+        );
+        assert_eq!(report.removed, vars(&[("DROP", "1")]));
+        assert_eq!(report.skipped, vec![String::from("PWD")]);
+        let mut strategy = ApplyStrategy::new(MergeStrategy::Overlay);
+        strategy.allow = Some(vec![String::from("ADD")]);
+        let report = plan(&current, &profile, &strategy);
+        assert_eq!(report.added, vars(&[("ADD", "1")]));
+        assert!(report.changed.is_empty());
+        assert!(report.removed.is_empty());
+        assert_eq!(
+            report.skipped,
+            vec![String::from("CHANGE"), String::from("PWD")]
+        );
+    }
+
+    #[test]
+    fn invalid_names() {
+        let current = vars(&[("=C:", "C:\\"), ("KEEP", "1")]);
+        let profile = vars(&[
+            ("KEEP", "1"),
+            ("", "empty"),
+            ("A=B", "1"),
+            ("NUL\0", "1"),
+            ("VALUE", "a\0b"),
+        ]);
+        let report = plan(
+            &current,
+            &profile,
+            &ApplyStrategy::new(MergeStrategy::Replace),
+        );
+        assert!(report.is_empty());
+        assert_eq!(
+            report.skipped,
+            vec!["", "=C:", "A=B", "NUL\0", "VALUE"]
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<String>>()
+        );
+    }
+
+    #[test]
+    fn not_loaded() {
+        let profile = Profile::new(&PathBuf::from("/bin/bash"), vec!["-c"], None)
+            .expect("Profile should be created");
+        assert!(matches!(
+            apply_to_current_process(&profile, ApplyStrategy::default()),
+            Err(Error::NotLoaded(_))
+        ));
+    }
+
+    #[test]
+    fn functions() {
+        let mut profile = Profile::new(&PathBuf::from("/bin/bash"), vec!["-c"], None)
+            .expect("Profile should be created");
+        profile.set_envvars(vars(&[
+            ("KEEP", "1"),
+            ("BASH_FUNC_same%%", "() {  echo same\n}"),
+            ("BASH_FUNC_new%%", "() {  echo new\n}"),
+        ]));
+        assert!(profile
+            .envvars
+            .as_ref()
+            .is_some_and(|envvars| envvars.len() == 1));
+        let current = vars(&[("KEEP", "1"), ("BASH_FUNC_same%%", "() {  echo same\n}")]);
+        let envvars = profile.raw_envvars().expect("Envvars should be loaded");
+        let report = plan(
+            &current,
+            &envvars,
+            &ApplyStrategy::new(MergeStrategy::Replace),
+        );
+        assert!(report.removed.is_empty());
+        assert_eq!(
+            report.added,
+            vars(&[("BASH_FUNC_new%%", "() {  echo new\n}")])
+        );
+    }
+
+    #[test]
+    fn poisoned() {
+        let _ = std::thread::spawn(|| {
+            let _guard = APPLYING.lock();
+            panic!("Lock should be poisoned");
+        })
+        .join();
+        let mut profile = Profile::new(&PathBuf::from("/bin/bash"), vec!["-c"], None)
+            .expect("Profile should be created");
+        // Nothing is changed: environment of the process is shared with other tests
+        profile.set_envvars(HashMap::new());
+        let report = apply_to_current_process(&profile, ApplyStrategy::new(MergeStrategy::Overlay))
+            .expect("Envvars should be applied");
+        assert!(report.added.is_empty() && report.changed.is_empty());
+        assert!(!APPLYING.is_poisoned());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn case_insensitive() {
+        let current = vars(&[("PATH", "C:\\Windows"), ("TEMP", "C:\\Temp")]);
+        let profile = vars(&[("Path", "C:\\Tools"), ("Temp", "C:\\Temp")]);
+        for merge in [MergeStrategy::Replace, MergeStrategy::Overlay] {
+            let report = plan(&current, &profile, &ApplyStrategy::new(merge));
+            assert!(report.added.is_empty());
+            assert!(report.removed.is_empty());
+            assert_eq!(
+                report.changed,
+                HashMap::from([(
+                    String::from("Path"),
+                    (String::from("C:\\Windows"), String::from("C:\\Tools"))
+                )])
+            );
+        }
+    }
+}
@@ -0,0 +1,746 @@
+use crate::{
+    analyzer::sourced,
+    checksum::checksum,
+    extractor::{Extractor, Options},
+    profiles::startup,
+    Error, Mode, Profile, ShellKind, EXTRACTOR,
+};
+use home::home_dir;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    env,
+    ffi::OsString,
+    fs,
+    fs::{remove_file, File, OpenOptions},
+    io,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(not(windows))]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+
+/// Default lifetime of entries in memory layer of cache
+const DEFAULT_TTL: Duration = Duration::from_secs(30);
+
+/// Entries on disk, which aren't used during this time, are removed
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Max number of entries on disk; the least recently used entries are removed
+const MAX_ENTRIES: usize = 64;
+
+/// Counter of temporary files, which makes names of temporary files unique in the
+/// process
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    #[doc(hidden)]
+    pub(crate) static ref CACHE: Cache = Cache::default();
+    #[doc(hidden)]
+    static ref REFRESHING: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    /// Path to the shell; entries of old versions don't have it
+    #[serde(default)]
+    shell: PathBuf,
+    /// Arguments of the shell; entries of old versions don't have it
+    #[serde(default)]
+    args: Vec<String>,
+    fingerprint: String,
+    envvars: HashMap<String, String>,
+}
+
+/// Entry of memory layer
+struct Remembered {
+    created: Instant,
+    shell: PathBuf,
+    args: Vec<String>,
+    envvars: HashMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct Key<'a> {
+    path: &'a PathBuf,
+    args: &'a [String],
+    mode: Mode,
+    cwd: Option<PathBuf>,
+    envs: &'a [(String, String)],
+    /// Values of `KEY_VARS` in the current process
+    environment: Vec<Option<OsString>>,
+}
+
+/// Variables of the current process, which affect the environment of the shell: the
+/// shell inherits them, they define startup files, which the shell reads, and
+/// locations of the user's files. Other variables (like `INVOCATION_ID` or
+/// `SSH_AUTH_SOCK`) usually are different for each start of the application and
+/// aren't considered.
+const KEY_VARS: [&str; 17] = [
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "LANG",
+    "LC_ALL",
+    "BASH_ENV",
+    "ENV",
+    "ZDOTDIR",
+    "XDG_CONFIG_HOME",
+    "XDG_CONFIG_DIRS",
+    "XDG_DATA_HOME",
+    "XDG_DATA_DIRS",
+    "USERPROFILE",
+    "APPDATA",
+    "LOCALAPPDATA",
+];
+
+fn default_dir() -> PathBuf {
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home_dir().map(|home| home.join(".cache")))
+    };
+    base.unwrap_or_else(env::temp_dir)
+        .join("envvars")
+        .join("profiles")
+}
+
+/// Files, which are read by the shell: startup files, files sourced by them (see
+/// `analyzer::sourced`) and the file defined with `BASH_ENV` (bash reads it, if it
+/// isn't interactive)
+fn tracked(profile: &Profile) -> Vec<PathBuf> {
+    let mode = profile.mode();
+    let mut files: Vec<PathBuf> = startup::files(profile.kind(), mode)
+        .into_iter()
+        .map(|file| file.path)
+        .collect();
+    if profile.kind() == ShellKind::Bash && !mode.interactive {
+        if let Some(path) = env::var_os("BASH_ENV").filter(|v| !v.is_empty()) {
+            files.push(PathBuf::from(path));
+        }
+    }
+    if !files.is_empty() {
+        for path in sourced(profile.kind(), mode) {
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+/// Fingerprint of tracked files of the shell: path, size, modification time and
+/// checksum of each existing file.
+fn fingerprint(files: &[PathBuf]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for file in files {
+        hasher.update(file.to_string_lossy().as_bytes());
+        let Ok(metadata) = fs::metadata(file) else {
+            hasher.update(b"-");
+            continue;
+        };
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        hasher.update(&metadata.len().to_le_bytes());
+        hasher.update(&modified.to_le_bytes());
+        hasher.update(checksum(file).unwrap_or_default().as_bytes());
+    }
+    hasher.finalize().to_string()
+}
+
+fn read(file: &PathBuf) -> Option<Entry> {
+    if !file.exists() {
+        return None;
+    }
+    match fs::read(file)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| serde_json::from_slice::<Entry>(&bytes).map_err(|e| e.to_string()))
+    {
+        Ok(entry) => Some(entry),
+        Err(err) => {
+            log::warn!("Fail to read cached envvars from {file:?}: {err}");
+            None
+        }
+    }
+}
+
+/// Checks the file is an entry of cache (or its temporary file): entries are named
+/// "{key}.json", where the key is blake3 hash (see `write`)
+fn is_entry(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let Some((key, extension)) = name.split_once('.') else {
+        return false;
+    };
+    key.len() == 64
+        && key.chars().all(|c| c.is_ascii_hexdigit())
+        && (extension == "json" || extension.ends_with(".tmp"))
+}
+
+/// Returns entries of cache (and temporary files) in the folder with time of last
+/// use
+fn entries(dir: &Path) -> Vec<(PathBuf, SystemTime)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| is_entry(path))
+        .filter_map(|path| {
+            let modified = fs::symlink_metadata(&path).ok()?.modified().ok()?;
+            Some((path, modified))
+        })
+        .collect()
+}
+
+/// Removes entries, which aren't used during `MAX_AGE`, and the least recently used
+/// entries above `MAX_ENTRIES`
+fn evict(dir: &Path) {
+    let mut entries = entries(dir);
+    entries.sort_by_key(|(_, modified)| Reverse(*modified));
+    let now = SystemTime::now();
+    for (i, (path, modified)) in entries.iter().enumerate() {
+        let outdated = now
+            .duration_since(*modified)
+            .map(|age| age > MAX_AGE)
+            .unwrap_or(false);
+        if i < MAX_ENTRIES && !outdated {
+            continue;
+        }
+        match remove_file(path) {
+            Ok(()) => log::debug!("Cached entry {path:?} is removed"),
+            Err(err) => log::debug!("Fail to remove cached entry {path:?}: {err}"),
+        }
+    }
+}
+
+/// Marks the entry as used right now (see `evict`)
+fn touch(file: &Path) {
+    if let Err(err) = File::options()
+        .write(true)
+        .open(file)
+        .and_then(|f| f.set_modified(SystemTime::now()))
+    {
+        log::debug!("Fail to update time of cached entry {file:?}: {err}");
+    }
+}
+
+/// Locks the state of cache. If a thread panicked while holding the lock, the
+/// state can be inconsistent: it's reset, entries are loaded again from disk or
+/// from the shell.
+fn lock<T: Default>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        log::warn!("Cache was locked by a panicked thread; state of cache is reset");
+        let mut guard = poisoned.into_inner();
+        *guard = T::default();
+        mutex.clear_poison();
+        guard
+    })
+}
+
+fn write(file: &PathBuf, entry: &Entry) -> Result<(), io::Error> {
+    let dir = file
+        .parent()
+        .ok_or(io::Error::other(format!("No parent folder for {file:?}")))?;
+    #[cfg(not(windows))]
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)?;
+    #[cfg(windows)]
+    fs::create_dir_all(dir)?;
+    let tmp = file.with_extension(format!(
+        "{}-{}.tmp",
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(not(windows))]
+    options.mode(0o600);
+    let mut output = options.open(&tmp)?;
+    output.write_all(&serde_json::to_vec(entry)?)?;
+    output.sync_all()?;
+    drop(output);
+    if let Err(err) = fs::rename(&tmp, file) {
+        let _ = remove_file(&tmp);
+        return Err(err);
+    }
+    evict(dir);
+    Ok(())
+}
+
+/// Cache of loaded environment variables. Cache has two layers:
+/// - memory layer, which keeps entries during defined time (TTL)
+/// - disk layer, which keeps entries until startup files of the shell aren't changed.
+///   The layer is used only by cache created with `Cache::new` or
+///   `Cache::persistent`.
+///
+/// Entries are identified by path to shell, shell's arguments, mode, current
+/// working folder and variables of the current process, which affect the shell
+/// (`PATH`, `HOME`, `SHELL`, `BASH_ENV`, `ZDOTDIR`, `XDG_CONFIG_HOME` etc).
+/// Each entry on disk is stored with a fingerprint of startup files of the shell
+/// and files sourced by them (size, modification time and checksum of files).
+/// Sourced files are found without execution (see `Profile::load_static`): a file
+/// sourced by a path, which cannot be resolved statically (like
+/// `. "$(brew --prefix)/etc/profile"`), isn't tracked and its changes don't
+/// invalidate the entry. If the fingerprint doesn't
+/// match anymore, cached variables are still returned, but the entry is refreshed
+/// in a background thread. If the shell doesn't read any startup file (for
+/// example, `bash -c`), only the memory layer is used. Entries on disk, which
+/// aren't used for 30 days, are removed; at most 64 entries are kept.
+///
+/// Entries on disk have values of all variables (including secrets like tokens)
+/// as plain text. On Unix files are readable only by the owner (the folder has
+/// mode 0700, files have 0600); on Windows files inherit ACL of the folder, so
+/// the folder should be accessible only by the user (like `%LOCALAPPDATA%`).
+pub struct Cache {
+    dir: Option<PathBuf>,
+    ttl: Duration,
+    memory: Mutex<HashMap<String, Remembered>>,
+}
+
+impl Cache {
+    /// Creates cache with memory and disk layers
+    /// * `dir` - folder to store cached entries
+    /// * `ttl` - lifetime of entries in memory layer
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        Cache {
+            dir: Some(dir),
+            ttl,
+            memory: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Creates cache, which keeps entries only in memory
+    /// * `ttl` - lifetime of entries
+    pub fn memory(ttl: Duration) -> Self {
+        Cache {
+            dir: None,
+            ttl,
+            memory: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Creates cache with memory and disk layers in user's cache folder
+    /// (`$XDG_CACHE_HOME/envvars/profiles` or `~/.cache/envvars/profiles` on Unix,
+    /// `%LOCALAPPDATA%\envvars\profiles` on Windows) with 30 seconds lifetime of
+    /// entries in memory. Files of the extractor can be placed into `envvars`
+    /// folder, but they aren't mixed with cached entries.
+    pub fn persistent() -> Self {
+        Self::new(default_dir(), DEFAULT_TTL)
+    }
+
+    fn key(&self, profile: &Profile, options: &Options) -> Result<String, Error> {
+        self.key_with(profile, options, |name| env::var_os(name))
+    }
+
+    /// Same as `key`, but takes variables of the current process from `environment`
+    fn key_with<F: Fn(&str) -> Option<OsString>>(
+        &self,
+        profile: &Profile,
+        options: &Options,
+        environment: F,
+    ) -> Result<String, Error> {
+        let key = Key {
+            path: &profile.path,
+            args: profile.args(),
+            mode: profile.mode(),
+            cwd: options.cwd.clone().or_else(|| env::current_dir().ok()),
+            envs: &options.envs,
+            environment: KEY_VARS.iter().map(|name| environment(name)).collect(),
+        };
+        let serialized = serde_json::to_vec(&key).map_err(Error::Serializing)?;
+        Ok(blake3::hash(&serialized).to_string())
+    }
+
+    fn file(&self, key: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(format!("{key}.json")))
+    }
+
+    fn remember(&self, key: String, profile: &Profile, envvars: &HashMap<String, String>) {
+        lock(&self.memory).insert(
+            key,
+            Remembered {
+                created: Instant::now(),
+                shell: profile.path.clone(),
+                args: profile.args().to_vec(),
+                envvars: envvars.clone(),
+            },
+        );
+    }
+
+    fn refresh(
+        &self,
+        profile: &Profile,
+        file: PathBuf,
+        fingerprint: String,
+        extractor: &Arc<Mutex<Extractor>>,
+        options: &Options,
+    ) -> Result<(), Error> {
+        if !lock(&REFRESHING).insert(file.clone()) {
+            return Ok(());
+        }
+        let mut profile = profile.clone();
+        let extractor = extractor.clone();
+        let options = options.clone();
+        thread::spawn(move || {
+            match profile.load_by(&extractor, &options) {
+                Ok(()) => {
+                    let entry = Entry::new(&profile, fingerprint);
+                    if let Err(err) = write(&file, &entry) {
+                        log::warn!("Fail to write cached envvars into {file:?}: {err}");
+                    }
+                }
+                Err(err) => {
+                    log::warn!("Fail to refresh envvars for {:?}: {err}", profile.path);
+                }
+            }
+            lock(&REFRESHING).remove(&file);
+        });
+        Ok(())
+    }
+
+    /// Loads environment variables of the profile using cache. If the cache doesn't
+    /// have an entry for the profile, `Profile::load` is called and results are saved
+    /// into the cache.
+    pub fn load(&self, profile: &mut Profile) -> Result<(), Error> {
+        self.load_by(profile, &EXTRACTOR, &Options::default())
+    }
+
+    /// Same as `load`, but uses the given extractor and options (see `Envvars`)
+    pub(crate) fn load_by(
+        &self,
+        profile: &mut Profile,
+        extractor: &Arc<Mutex<Extractor>>,
+        options: &Options,
+    ) -> Result<(), Error> {
+        let key = self.key(profile, options)?;
+        if let Some(remembered) = lock(&self.memory).get(&key) {
+            if remembered.created.elapsed() < self.ttl {
+                profile.set_envvars(remembered.envvars.clone());
+                return Ok(());
+            }
+        }
+        let disk = self
+            .file(&key)
+            .map(|file| (file, tracked(profile)))
+            .filter(|(_, files)| !files.is_empty());
+        let Some((file, files)) = disk else {
+            // Disk layer isn't used or nothing shows that cached variables are
+            // outdated: only memory layer (with TTL) is used
+            profile.load_by(extractor, options)?;
+            self.remember(key, profile, &profile.raw_envvars().unwrap_or_default());
+            return Ok(());
+        };
+        let fingerprint = fingerprint(&files);
+        if let Some(entry) = read(&file) {
+            if entry.fingerprint == fingerprint {
+                touch(&file);
+                self.remember(key, profile, &entry.envvars);
+            } else {
+                log::debug!("Startup files of {:?} are changed", profile.path);
+                self.refresh(profile, file, fingerprint, extractor, options)?;
+            }
+            profile.set_envvars(entry.envvars);
+            return Ok(());
+        }
+        profile.load_by(extractor, options)?;
+        let entry = Entry::new(profile, fingerprint);
+        if let Err(err) = write(&file, &entry) {
+            log::warn!("Fail to write cached envvars into {file:?}: {err}");
+        }
+        self.remember(key, profile, &entry.envvars);
+        Ok(())
+    }
+
+    /// Removes cached entries of the profile (the same shell with the same
+    /// arguments) from memory and disk. Entries created with any options (like
+    /// `Envvars::with_env` or `Envvars::with_cwd`) are removed.
+    pub fn invalidate(&self, profile: &Profile) -> Result<(), Error> {
+        lock(&self.memory).retain(|_, remembered| {
+            remembered.shell != profile.path || remembered.args != profile.args()
+        });
+        let Some(dir) = self.dir.as_ref() else {
+            return Ok(());
+        };
+        for (file, _) in entries(dir) {
+            let Some(entry) = read(&file) else {
+                continue;
+            };
+            if entry.shell == profile.path && entry.args == profile.args() {
+                remove_file(&file).map_err(Error::Io)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes all cached entries from memory and disk. Only files of entries are
+    /// removed, the folder of the cache and other files in it are kept.
+    pub fn clear(&self) -> Result<(), Error> {
+        lock(&self.memory).clear();
+        let Some(dir) = self.dir.as_ref() else {
+            return Ok(());
+        };
+        for (file, _) in entries(dir) {
+            remove_file(&file).map_err(Error::Io)?;
+        }
+        Ok(())
+    }
+}
+
+impl Entry {
+    fn new(profile: &Profile, fingerprint: String) -> Self {
+        Entry {
+            shell: profile.path.clone(),
+            args: profile.args().to_vec(),
+            fingerprint,
+            envvars: profile.raw_envvars().unwrap_or_default(),
+        }
+    }
+}
+
+impl Default for Cache {
+    /// Creates cache, which keeps entries only in memory during 30 seconds. Use
+    /// `Cache::persistent` to keep entries on disk too.
+    fn default() -> Self {
+        Self::memory(DEFAULT_TTL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{profiles::get as get_profiles, testing::TempDir};
+
+    fn shell() -> Profile {
+        get_profiles()
+            .expect("Profiles should be detected")
+            .into_iter()
+            .find(|p| matches!(p.kind(), ShellKind::Bash | ShellKind::Sh))
+            .expect("bash or sh should be detected")
+    }
+
+    fn entry_file(cache: &Cache, key: &str) -> PathBuf {
+        cache.file(key).expect("Disk layer should be used")
+    }
+
+    fn entry(profile: &Profile) -> Entry {
+        Entry {
+            shell: profile.path.clone(),
+            args: profile.args().to_vec(),
+            fingerprint: String::new(),
+            envvars: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test() {
+        let temp = TempDir::new("cache-test");
+        let dir = temp.path().to_path_buf();
+        let cache = Cache::new(dir.clone(), Duration::from_secs(60));
+        let shell = shell();
+        // Shell doesn't read startup files with "-c": only memory layer is used
+        let mut profile = shell.clone();
+        cache.load(&mut profile).expect("Envvars should be loaded");
+        assert!(profile.envvars.is_some());
+        let key = cache
+            .key(&profile, &Options::default())
+            .expect("Key should be created");
+        assert!(!entry_file(&cache, &key).exists());
+        // Login shell reads startup files: entry is written on disk
+        let mut profile =
+            Profile::new(&shell.path, vec!["-l", "-c"], None).expect("Profile should be created");
+        cache.load(&mut profile).expect("Envvars should be loaded");
+        let file = entry_file(
+            &cache,
+            &cache
+                .key(&profile, &Options::default())
+                .expect("Key should be created"),
+        );
+        assert!(file.exists());
+        // Served from disk
+        cache.memory.lock().expect("Access to memory").clear();
+        let mut cached = profile.clone();
+        cached.envvars = None;
+        cache.load(&mut cached).expect("Envvars should be loaded");
+        assert_eq!(cached.envvars, profile.envvars);
+        cache.invalidate(&profile).expect("Entry should be removed");
+        assert!(!file.exists());
+        cache.clear().expect("Cache should be removed");
+        assert!(entries(&dir).is_empty());
+    }
+
+    #[test]
+    fn memory_only() {
+        let cache = Cache::memory(Duration::from_secs(60));
+        let shell = shell();
+        let mut profile =
+            Profile::new(&shell.path, vec!["-l", "-c"], None).expect("Profile should be created");
+        cache.load(&mut profile).expect("Envvars should be loaded");
+        assert!(profile.envvars.is_some());
+        let key = cache
+            .key(&profile, &Options::default())
+            .expect("Key should be created");
+        assert!(cache.file(&key).is_none());
+        assert!(cache
+            .memory
+            .lock()
+            .expect("Access to memory")
+            .contains_key(&key));
+        cache.invalidate(&profile).expect("Entry should be removed");
+        assert!(cache.memory.lock().expect("Access to memory").is_empty());
+    }
+
+    #[test]
+    fn environment() {
+        let cache = Cache::memory(Duration::from_secs(60));
+        let profile = shell();
+        let key = |name: &'static str, value: &'static str| {
+            cache
+                .key_with(&profile, &Options::default(), move |var| {
+                    if var == name {
+                        Some(OsString::from(value))
+                    } else {
+                        env::var_os(var)
+                    }
+                })
+                .expect("Key should be created")
+        };
+        let current = cache
+            .key(&profile, &Options::default())
+            .expect("Key should be created");
+        // Variables, which are unique for each start of the application, are ignored
+        assert_eq!(key("INVOCATION_ID", "changed"), current);
+        assert_eq!(key("SSH_AUTH_SOCK", "/tmp/changed"), current);
+        // Variables, which affect the shell, give another entry
+        assert_ne!(key("PATH", "/changed"), current);
+        assert_ne!(key("ZDOTDIR", "/changed"), current);
+    }
+
+    #[test]
+    fn invalidate_with_options() {
+        let temp = TempDir::new("cache-invalidate");
+        let dir = temp.path().to_path_buf();
+        let cache = Cache::new(dir.clone(), Duration::from_secs(60));
+        let shell = shell();
+        let mut profile =
+            Profile::new(&shell.path, vec!["-l", "-c"], None).expect("Profile should be created");
+        let options = Options {
+            envs: vec![(String::from("ENVVARS_CACHE_TEST"), String::from("1"))],
+            cwd: Some(env::temp_dir()),
+            ..Default::default()
+        };
+        cache
+            .load_by(&mut profile, &EXTRACTOR, &options)
+            .expect("Envvars should be loaded");
+        let file = entry_file(
+            &cache,
+            &cache
+                .key(&profile, &options)
+                .expect("Key should be created"),
+        );
+        assert!(file.exists());
+        // Entry of another profile is kept
+        let other = entry_file(&cache, &"0".repeat(64));
+        write(&other, &entry(&shell)).expect("Entry should be written");
+        cache.invalidate(&profile).expect("Entry should be removed");
+        assert!(!file.exists());
+        assert!(other.exists());
+        assert!(cache.memory.lock().expect("Access to memory").is_empty());
+    }
+
+    #[test]
+    fn clear_keeps_other_files() {
+        let temp = TempDir::new("cache-clear");
+        let dir = temp.path().to_path_buf();
+        let cache = Cache::new(dir.clone(), Duration::from_secs(60));
+        let file = entry_file(&cache, &"a".repeat(64));
+        write(&file, &entry(&shell())).expect("Entry should be written");
+        let other = dir.join("other.json");
+        fs::write(&other, "{}").expect("File should be written");
+        cache.clear().expect("Cache should be cleared");
+        assert!(!file.exists());
+        assert!(other.exists());
+    }
+
+    #[test]
+    fn eviction() {
+        let temp = TempDir::new("cache-evict");
+        let dir = temp.path().to_path_buf();
+        let cache = Cache::new(dir.clone(), Duration::from_secs(60));
+        let profile = shell();
+        for i in 0..MAX_ENTRIES + 2 {
+            write(&entry_file(&cache, &format!("{i:064x}")), &entry(&profile))
+                .expect("Entry should be written");
+        }
+        assert_eq!(entries(&dir).len(), MAX_ENTRIES);
+        // Entry, which isn't used during MAX_AGE, is removed
+        let outdated = entry_file(&cache, &format!("{:064x}", MAX_ENTRIES + 1));
+        File::options()
+            .write(true)
+            .open(&outdated)
+            .and_then(|f| f.set_modified(SystemTime::now() - MAX_AGE * 2))
+            .expect("Time should be changed");
+        evict(&dir);
+        assert!(!outdated.exists());
+        assert_eq!(entries(&dir).len(), MAX_ENTRIES - 1);
+    }
+
+    #[test]
+    fn unique_temp_files() {
+        let temp = TempDir::new("cache-tmp");
+        let dir = temp.path().to_path_buf();
+        let file = dir.join(format!("{}.json", "b".repeat(64)));
+        let profile = shell();
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| write(&file, &entry(&profile)).expect("Entry should be written"));
+            }
+        });
+        assert!(read(&file).is_some());
+        assert_eq!(entries(&dir).len(), 1);
+    }
+
+    #[test]
+    fn poisoned() {
+        let temp = TempDir::new("cache-poisoned");
+        let dir = temp.path().to_path_buf();
+        let cache = Cache::new(dir.clone(), Duration::from_secs(60));
+        let profile = shell();
+        cache.remember(String::from("key"), &profile, &HashMap::new());
+        thread::scope(|scope| {
+            let _ = scope
+                .spawn(|| {
+                    let _guard = cache.memory.lock();
+                    panic!("Memory of cache should be poisoned");
+                })
+                .join();
+        });
+        assert!(cache.memory.is_poisoned());
+        // Memory is reset and the cache works as usual
+        cache
+            .invalidate(&profile)
+            .expect("Cache should be invalidated");
+        assert!(!cache.memory.is_poisoned());
+        cache.remember(String::from("key"), &profile, &HashMap::new());
+        cache.clear().expect("Cache should be cleared");
+        assert!(lock(&cache.memory).is_empty());
+    }
+}
@@ -1,7 +1,11 @@
 use std::{fs::File, io, io::Read, path::PathBuf};
 
 pub fn checksum(filename: &PathBuf) -> Result<String, io::Error> {
-    let mut file = File::open(filename)?;
+    checksum_of(&mut File::open(filename)?)
+}
+
+/// Calculates checksum of already opened file
+pub fn checksum_of(file: &mut File) -> Result<String, io::Error> {
     let mut hasher = blake3::Hasher::new();
     let mut buffer = [0; 65536];
     loop {
@@ -0,0 +1,157 @@
+use crate::{
+    functions::encode,
+    merge::{is_path_list, merge_paths, MergeStrategy},
+    Error, Profile,
+};
+use std::{env, process::Command};
+
+/// Extension of `std::process::Command`, which allows to run a command with
+/// environment variables of a shell's profile.
+pub trait CommandExt {
+    /// Sets environment variables of the profile to the command. If variables of
+    /// the profile haven't been loaded yet, `Profile::load` will be called.
+    /// * `profile` - shell's profile to take environment variables from
+    /// * `strategy` - defines how variables of the profile are combined with the
+    ///   environment of the command (current environment and variables, which were
+    ///   already set to the command with `env`, `envs` or `env_remove`). With
+    ///   `MergeStrategy::Replace` the environment of the command is cleared; with
+    ///   overlay strategies only variables of the profile are set, everything else
+    ///   (including variables, which aren't valid unicode) is inherited as is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{path::PathBuf, process::Command, str::FromStr};
+    /// use envvars::{CommandExt, MergeStrategy, Profile};
+    ///
+    /// let mut profile: Profile = if cfg!(windows) {
+    ///     Profile::new(&PathBuf::from_str("C:\\Program Files\\Git\\bin\\bash.exe").unwrap(), vec!["-c"], None).unwrap()
+    /// } else {
+    ///     Profile::new(&PathBuf::from_str("/bin/bash").unwrap(), vec!["-c"], None).unwrap()
+    /// };
+    ///
+    /// let mut command = Command::new("cargo");
+    /// command
+    ///     .arg("--version")
+    ///     .envs_from_profile(&mut profile, MergeStrategy::OverlayPaths)
+    ///     .unwrap();
+    ///
+    /// assert!(profile.envvars.is_some());
+    /// ```
+    fn envs_from_profile(
+        &mut self,
+        profile: &mut Profile,
+        strategy: MergeStrategy,
+    ) -> Result<&mut Self, Error>;
+}
+
+/// Returns the value of variable, which the command would get: the value set with
+/// `env`, nothing if it's removed with `env_remove`, or the value of the current
+/// process
+fn inherited(command: &Command, key: &str) -> Option<String> {
+    let explicit = command.get_envs().find(|(k, _)| {
+        if cfg!(windows) {
+            k.to_string_lossy().eq_ignore_ascii_case(key)
+        } else {
+            *k == key
+        }
+    });
+    match explicit {
+        Some((_, value)) => value.and_then(|v| v.to_str().map(String::from)),
+        None => env::var(key).ok(),
+    }
+}
+
+impl CommandExt for Command {
+    fn envs_from_profile(
+        &mut self,
+        profile: &mut Profile,
+        strategy: MergeStrategy,
+    ) -> Result<&mut Self, Error> {
+        let mut envvars = profile.loaded()?.clone();
+        // Exported functions of bash are passed to the command as well
+        if let Some(functions) = profile.functions.as_ref() {
+            envvars.extend(encode(functions));
+        }
+        match strategy {
+            MergeStrategy::Replace => Ok(self.env_clear().envs(envvars)),
+            MergeStrategy::Overlay => Ok(self.envs(envvars)),
+            MergeStrategy::OverlayPaths => {
+                for (key, value) in envvars.iter_mut().filter(|(k, _)| is_path_list(k)) {
+                    if let Some(current) = inherited(self, key) {
+                        *value = merge_paths(value, &current);
+                    }
+                }
+                Ok(self.envs(envvars))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, ffi::OsStr, path::PathBuf};
+
+    fn profile() -> Profile {
+        let mut profile = Profile::new(&PathBuf::from("/bin/bash"), vec!["-c"], None)
+            .expect("Profile should be created");
+        profile.set_envvars(HashMap::from([
+            (String::from("ENVVARS_COMMAND"), String::from("1")),
+            (String::from("PATH"), String::from("/opt/bin")),
+        ]));
+        profile
+    }
+
+    fn explicit(command: &Command) -> HashMap<String, Option<String>> {
+        command
+            .get_envs()
+            .map(|(k, v)| {
+                (
+                    k.to_string_lossy().to_string(),
+                    v.map(|v| v.to_string_lossy().to_string()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn overlay() {
+        let mut command = Command::new("true");
+        command
+            .env_remove("ENVVARS_REMOVED")
+            .env("PATH", "/usr/bin:/opt/bin")
+            .envs_from_profile(&mut profile(), MergeStrategy::OverlayPaths)
+            .expect("Envvars should be set");
+        let envs = explicit(&command);
+        // Only variables of the profile are set, the rest is inherited
+        assert_eq!(envs.len(), 3);
+        assert_eq!(envs["ENVVARS_REMOVED"], None);
+        assert_eq!(envs["ENVVARS_COMMAND"].as_deref(), Some("1"));
+        assert_eq!(envs["PATH"].as_deref(), Some("/opt/bin:/usr/bin"));
+        let mut command = Command::new("true");
+        command
+            .env("PATH", "/usr/bin")
+            .envs_from_profile(&mut profile(), MergeStrategy::Overlay)
+            .expect("Envvars should be set");
+        assert_eq!(explicit(&command)["PATH"].as_deref(), Some("/opt/bin"));
+    }
+
+    #[test]
+    fn replace() {
+        use std::os::unix::ffi::OsStrExt;
+        let mut command = Command::new("/usr/bin/env");
+        command
+            .env(
+                OsStr::from_bytes(b"ENVVARS_BYTES"),
+                OsStr::from_bytes(b"\xff"),
+            )
+            .envs_from_profile(&mut profile(), MergeStrategy::Replace)
+            .expect("Envvars should be set");
+        let output = command.output().expect("Command should be executed");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("ENVVARS_COMMAND=1"));
+        assert!(!stdout.contains("ENVVARS_BYTES"));
+    }
+}
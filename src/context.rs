@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf};
+
+/// Limit of a resource (`ulimit`). `None` means unlimited.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rlimit {
+    /// Soft limit, which is applied to the process
+    pub soft: Option<u64>,
+    /// Hard limit, the ceiling for the soft limit
+    pub hard: Option<u64>,
+}
+
+/// Context of a process started by the shell after all startup files were applied.
+/// Startup files can change not only environment variables, but also `umask`,
+/// limits of resources (`ulimit`), working folder and locale.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShellContext {
+    /// Working folder
+    pub cwd: Option<PathBuf>,
+    /// File mode creation mask. Unix only
+    pub umask: Option<u32>,
+    /// Limits of resources by names: "core", "cpu", "data", "fsize", "memlock",
+    /// "nofile", "nproc", "stack", "as". Unix only
+    pub rlimits: HashMap<String, Rlimit>,
+    /// Effective locale by categories ("LC_CTYPE", "LC_MESSAGES" etc). On unix the
+    /// locale is applied, so a locale, which isn't installed in the system, is
+    /// reported as "C".
+    pub locale: HashMap<String, String>,
+}
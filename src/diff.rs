@@ -0,0 +1,60 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Difference between two sets of environment variables
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvDiff {
+    /// Variables, which exist only in the second set
+    pub added: HashMap<String, String>,
+    /// Variables with different values: (value in the first set, value in the second set)
+    pub changed: HashMap<String, (String, String)>,
+    /// Variables, which exist only in the first set
+    pub removed: HashMap<String, String>,
+}
+
+impl EnvDiff {
+    /// Compares two sets of environment variables
+    /// * `before` - the first (base) set
+    /// * `after` - the second set
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use envvars::EnvDiff;
+    ///
+    /// let before = HashMap::from([(String::from("A"), String::from("1"))]);
+    /// let after = HashMap::from([(String::from("B"), String::from("2"))]);
+    /// let diff = EnvDiff::between(&before, &after);
+    ///
+    /// assert!(diff.added.contains_key("B"));
+    /// assert!(diff.removed.contains_key("A"));
+    /// assert!(diff.changed.is_empty());
+    /// ```
+    pub fn between(before: &HashMap<String, String>, after: &HashMap<String, String>) -> Self {
+        let mut diff = EnvDiff::default();
+        for (key, value) in after.iter() {
+            match before.get(key) {
+                Some(prev) if prev == value => {}
+                Some(prev) => {
+                    diff.changed
+                        .insert(key.clone(), (prev.clone(), value.clone()));
+                }
+                None => {
+                    diff.added.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        for (key, value) in before.iter() {
+            if !after.contains_key(key) {
+                diff.removed.insert(key.clone(), value.clone());
+            }
+        }
+        diff
+    }
+
+    /// Returns true if both sets are equal
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
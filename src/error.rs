@@ -1,25 +1,163 @@
-use std::{io, path::PathBuf};
+use std::{fmt, io, path::PathBuf, process::Output, time::Duration};
+
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+
+/// Max length of stdout and stderr kept in `Diagnostics`
+const OUTPUT_LIMIT: usize = 4096;
+
+/// Cuts the output to `OUTPUT_LIMIT` bytes (on the boundary of char)
+pub(crate) fn truncate(output: &str) -> String {
+    if output.len() <= OUTPUT_LIMIT {
+        return output.to_owned();
+    }
+    let mut end = OUTPUT_LIMIT;
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!(
+        "{}... ({} bytes are truncated)",
+        &output[..end],
+        output.len() - end
+    )
+}
+
+/// Classification of errors, which allows to show an actionable message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Shell or file isn't found
+    NotFound,
+    /// Access is denied
+    PermissionDenied,
+    /// Shell cannot be started
+    Spawn,
+    /// Shell is terminated by a signal (unix only)
+    Signaled,
+    /// Shell exited with non-zero code
+    ExitFailure,
+    /// Output of the shell (or of the extractor) cannot be decoded or parsed
+    InvalidOutput,
+    /// Extractor cannot be created
+    Delivery,
+    /// Operation, shell or platform isn't supported
+    NotSupported,
+    /// Invalid input, like a name of variable
+    InvalidInput,
+    /// Any other errors
+    Other,
+}
+
+/// Details of running the shell, which help to understand a failure
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct Diagnostics {
+    /// Path to the shell. `None` if the extractor is started without shell
+    pub shell: Option<PathBuf>,
+    /// Arguments of the shell
+    pub args: Vec<String>,
+    /// Exit code of the shell
+    pub code: Option<i32>,
+    /// Signal, which terminated the shell (unix only)
+    pub signal: Option<i32>,
+    /// Beginning of stdout of the shell (up to 4Kb)
+    pub stdout: String,
+    /// Beginning of stderr of the shell (up to 4Kb)
+    pub stderr: String,
+    /// Time of running the shell
+    pub elapsed: Option<Duration>,
+}
+
+impl Diagnostics {
+    pub(crate) fn new(shell: Option<&PathBuf>, args: &[String]) -> Self {
+        Diagnostics {
+            shell: shell.cloned(),
+            args: args.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds exit status, stdout and stderr of the shell
+    pub(crate) fn with_output(mut self, output: &Output) -> Self {
+        self.code = output.status.code();
+        #[cfg(unix)]
+        {
+            self.signal = output.status.signal();
+        }
+        self.stdout = truncate(&String::from_utf8_lossy(&output.stdout));
+        self.stderr = truncate(&String::from_utf8_lossy(&output.stderr));
+        self
+    }
+
+    /// Adds stdout of the shell
+    pub(crate) fn with_stdout(mut self, stdout: &str) -> Self {
+        self.stdout = truncate(stdout);
+        self
+    }
+
+    /// Adds stderr of the shell
+    pub(crate) fn with_stderr(mut self, stderr: &str) -> Self {
+        self.stderr = truncate(stderr);
+        self
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.shell.as_ref() {
+            Some(shell) => write!(f, "shell: {shell:?}; args: {:?}", self.args)?,
+            None => write!(f, "without shell")?,
+        }
+        if let Some(code) = self.code {
+            write!(f, "; exit code: {code}")?;
+        }
+        if let Some(signal) = self.signal {
+            write!(f, "; signal: {signal}")?;
+        }
+        if let Some(elapsed) = self.elapsed {
+            write!(f, "; elapsed: {elapsed:?}")?;
+        }
+        if !self.stderr.is_empty() {
+            write!(f, "; stderr: {:?}", self.stderr)?;
+        }
+        Ok(())
+    }
+}
 
 /// Definition of shell profile
 #[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
-    /// PoisonError error
-    #[error("PoisonError: {0:?}")]
-    PoisonError(String),
     /// IO related error
     #[error("IO Error: {0:?}")]
     Io(io::Error),
     /// Happens if stdout has content, which isn't possible to parse as json
     /// string.
-    #[error("Parsing error: {0:?}")]
-    Parsing(serde_json::Error, Option<i32>, String, String),
+    #[error("Parsing error: {source}; {diagnostics}")]
+    #[non_exhaustive]
+    Parsing {
+        source: serde_json::Error,
+        diagnostics: Box<Diagnostics>,
+    },
     /// Any error during attempt to execute extractor as target shell command
-    #[error("Fail to execute extractor: {0:?}")]
-    Executing(io::Error),
-    /// Happens if by some reasons isn't possible to create extractor in system
-    /// temporary folder
-    #[error("Fail to create extractor: {0:?}")]
-    Create(io::Error),
+    #[error("Fail to execute extractor: {source}; {diagnostics}")]
+    #[non_exhaustive]
+    Executing {
+        source: io::Error,
+        diagnostics: Box<Diagnostics>,
+    },
+    /// Happens if by some reasons isn't possible to create extractor in the
+    /// folder of extractor
+    #[error("Fail to create extractor (location: {location:?}): {source}")]
+    #[non_exhaustive]
+    Create {
+        source: io::Error,
+        location: Option<PathBuf>,
+    },
+    /// Data (like a key of cached entry) cannot be serialized, for example, because
+    /// a path isn't a valid UTF-8 string
+    #[error("Fail to serialize: {0}")]
+    Serializing(serde_json::Error),
     /// Will be dropped if attempt to decode stdout or stderr of shell child process
     /// is failed
     #[error("Fail to decode stdout/stderr: {0:?}")]
@@ -27,9 +165,19 @@ pub enum Error {
     /// Shell executable file doesn't exist
     #[error("Shell executor isn't found: {0:?}")]
     NotFound(PathBuf),
+    /// Process with the given pid doesn't exist (or is already finished)
+    #[error("Process isn't found: {0}")]
+    ProcessNotFound(u32),
+    /// Access to the file is denied, for example, attempt to read environment
+    /// variables of a process, which belongs to another user
+    #[error("Permission denied: {0:?}")]
+    PermissionDenied(PathBuf),
     /// Target platform isn't supported
     #[error("Platform isn't supported")]
     NotSupportedPlatform,
+    /// Requested operation isn't supported for this kind of shell
+    #[error("Operation isn't supported for shell: {0}")]
+    NotSupportedShell(String),
     /// Happends on errors related converting paths to strings
     #[error("Infallible: {0}")]
     Infallible(std::convert::Infallible),
@@ -38,7 +186,165 @@ pub enum Error {
     /// variables aren't found
     #[error("Fail to find envvar: {0}")]
     NotFoundEnvVar(String),
+    /// Environment variables of the profile aren't loaded yet (see `Profile::load`)
+    #[error("Envvars of {0:?} aren't loaded")]
+    NotLoaded(PathBuf),
+    /// Name of variable isn't valid or value cannot be written into startup file
+    /// (for example, it has a line break)
+    #[error("Invalid variable: {0}")]
+    InvalidVariable(String),
+    /// Block managed by `envvars` in the startup file isn't closed: the last line
+    /// of the block (`# <<< envvars <<<`) is missing
+    #[error("Block managed by envvars isn't closed in {0:?}")]
+    UnclosedBlock(PathBuf),
+    /// Variable was written into startup file, but after reloading the shell it
+    /// has another value. Usually it means the variable is overwritten by another
+    /// startup file, which is read later.
+    #[error("Variable {name} isn't applied after writing into {path:?}; {diagnostics}")]
+    #[non_exhaustive]
+    NotPersisted {
+        name: String,
+        path: PathBuf,
+        diagnostics: Box<Diagnostics>,
+    },
+    /// Response of the extractor is invalid: unsupported version of protocol,
+    /// unexpected checksum of the extractor, envelope isn't found etc.
+    #[error("Invalid response of extractor: {message}; {diagnostics}")]
+    #[non_exhaustive]
+    Protocol {
+        message: String,
+        diagnostics: Box<Diagnostics>,
+    },
     /// Any other errors
     #[error("Other: {0}")]
     Other(String),
 }
+
+impl Error {
+    /// Returns classification of the error
+    pub fn kind(&self) -> ErrorKind {
+        let io = |err: &io::Error, default: ErrorKind| match err.kind() {
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            _ => default,
+        };
+        match self {
+            Error::Parsing { diagnostics, .. } | Error::Executing { diagnostics, .. }
+                if diagnostics.signal.is_some() =>
+            {
+                ErrorKind::Signaled
+            }
+            Error::Parsing { diagnostics, .. } | Error::Executing { diagnostics, .. }
+                if diagnostics.code.is_some_and(|code| code != 0) =>
+            {
+                ErrorKind::ExitFailure
+            }
+            Error::Parsing { .. } => ErrorKind::InvalidOutput,
+            Error::Executing { source, .. } => io(source, ErrorKind::Spawn),
+            Error::Create { source, .. } => io(source, ErrorKind::Delivery),
+            Error::Io(err) => io(err, ErrorKind::Other),
+            Error::Decoding(_) | Error::Protocol { .. } => ErrorKind::InvalidOutput,
+            Error::NotFound(_) | Error::ProcessNotFound(_) | Error::NotFoundEnvVar(_) => {
+                ErrorKind::NotFound
+            }
+            Error::PermissionDenied(_) => ErrorKind::PermissionDenied,
+            Error::NotSupportedPlatform | Error::NotSupportedShell(_) => ErrorKind::NotSupported,
+            Error::InvalidVariable(_) | Error::Serializing(_) | Error::NotLoaded(_) => {
+                ErrorKind::InvalidInput
+            }
+            Error::Infallible(_)
+            | Error::UnclosedBlock(_)
+            | Error::NotPersisted { .. }
+            | Error::Other(_) => ErrorKind::Other,
+        }
+    }
+
+    /// Returns details of running the shell, if the error is related to it
+    pub fn diagnostics(&self) -> Option<&Diagnostics> {
+        match self {
+            Error::Parsing { diagnostics, .. }
+            | Error::Executing { diagnostics, .. }
+            | Error::Protocol { diagnostics, .. }
+            | Error::NotPersisted { diagnostics, .. } => Some(diagnostics),
+            _ => None,
+        }
+    }
+
+    /// Adds the shell, its arguments and time of running into diagnostics of the
+    /// error
+    pub(crate) fn with_command(
+        mut self,
+        shell: Option<&PathBuf>,
+        args: &[String],
+        elapsed: Duration,
+    ) -> Self {
+        if let Error::Parsing { diagnostics, .. }
+        | Error::Executing { diagnostics, .. }
+        | Error::Protocol { diagnostics, .. }
+        | Error::NotPersisted { diagnostics, .. } = &mut self
+        {
+            diagnostics.shell = shell.cloned();
+            diagnostics.args = args.to_vec();
+            diagnostics.elapsed = Some(elapsed);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let long = "é".repeat(OUTPUT_LIMIT);
+        let cut = truncate(&long);
+        assert!(cut.len() < long.len());
+        assert!(cut.ends_with(&format!(
+            "({} bytes are truncated)",
+            long.len() - OUTPUT_LIMIT
+        )));
+        let source = serde_json::from_str::<u8>("-").expect_err("JSON should be invalid");
+        let err = Error::Parsing {
+            source,
+            diagnostics: Box::new(Diagnostics {
+                code: Some(1),
+                ..Default::default()
+            }),
+        }
+        .with_command(Some(&PathBuf::from("/bin/sh")), &[], Duration::ZERO);
+        assert_eq!(err.kind(), ErrorKind::ExitFailure);
+        assert_eq!(
+            err.diagnostics().and_then(|d| d.shell.clone()),
+            Some(PathBuf::from("/bin/sh"))
+        );
+    }
+
+    #[test]
+    fn struct_variants() {
+        let shell = PathBuf::from("/bin/sh");
+        let args = vec!["-l".to_owned()];
+        let err = Error::Protocol {
+            message: String::from("checksum of extractor doesn't match"),
+            diagnostics: Box::new(Diagnostics::default().with_stderr("warning")),
+        }
+        .with_command(Some(&shell), &args, Duration::from_secs(1));
+        assert_eq!(err.kind(), ErrorKind::InvalidOutput);
+        let diagnostics = err.diagnostics().expect("Diagnostics should be kept");
+        assert_eq!(diagnostics.shell.as_ref(), Some(&shell));
+        assert_eq!(diagnostics.args, args);
+        assert_eq!(diagnostics.stderr, "warning");
+        assert!(err.to_string().contains("stderr: \"warning\""));
+        let err = Error::NotPersisted {
+            name: String::from("EDITOR"),
+            path: PathBuf::from("/root/.bashrc"),
+            diagnostics: Box::default(),
+        }
+        .with_command(Some(&shell), &args, Duration::from_secs(1));
+        assert_eq!(err.kind(), ErrorKind::Other);
+        assert_eq!(
+            err.diagnostics().and_then(|d| d.elapsed),
+            Some(Duration::from_secs(1))
+        );
+    }
+}
@@ -1,33 +1,499 @@
-use crate::{assets, checksum::checksum, Error};
+use crate::{
+    assets,
+    checksum::checksum_of,
+    context::ShellContext,
+    error::Diagnostics,
+    protocol::{Envelope, Request},
+    Error, EXTRACTOR,
+};
+use home::home_dir;
 use std::{
     collections::HashMap,
-    env::temp_dir,
-    fs::{remove_file, File, OpenOptions},
+    env::{self, temp_dir},
+    fs::{read, read_dir, remove_file, rename, DirBuilder, File, Metadata, OpenOptions},
     io,
     io::Write,
     path::{Path, PathBuf},
-    process::{Command, Output},
+    process::{Command, Output, Stdio},
     str::from_utf8,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 #[cfg(not(windows))]
-use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+
+#[cfg(target_os = "linux")]
+use std::os::fd::AsRawFd;
 
 #[cfg(windows)]
 use std::os::windows::process::CommandExt;
 
+/// Prefix of names of extractor's files (see `build/injector.rs`)
+const PREFIX: &str = "envvars-extractor-";
+
+/// Signature of the extractor (see `assets/extractor`)
+const MAGIC: &[u8] = b"ENVVARS_EXTRACTOR_MAGIC|1";
+
+/// Environment variable, which defines the folder of the extractor
+pub const EXTRACTOR_DIR_VAR: &str = "ENVVARS_EXTRACTOR_DIR";
+
 #[cfg(windows)]
-fn get_extractor_path() -> PathBuf {
-    temp_dir().join(Path::new(&format!("{}.exe", assets::filename())))
+fn file_name() -> String {
+    format!("{}.exe", assets::filename())
 }
 
 #[cfg(not(windows))]
-fn get_extractor_path() -> PathBuf {
-    temp_dir().join(Path::new(assets::filename()))
+fn file_name() -> String {
+    assets::filename().to_owned()
+}
+
+/// Returns folders, which can be used for the extractor, in order of priority:
+/// the folder defined with `set_extractor_dir`, the folder defined with
+/// `ENVVARS_EXTRACTOR_DIR`, `$XDG_RUNTIME_DIR/envvars` (unix), the cache folder
+/// (`~/.cache/envvars` or `%LOCALAPPDATA%\envvars`) and a private folder in the
+/// temporary folder.
+fn candidates(custom: Option<&PathBuf>) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = custom.cloned().into_iter().collect();
+    if let Some(dir) = env::var_os(EXTRACTOR_DIR_VAR).filter(|v| !v.is_empty()) {
+        dirs.push(PathBuf::from(dir));
+    }
+    #[cfg(windows)]
+    {
+        if let Some(dir) = env::var_os("LOCALAPPDATA") {
+            dirs.push(PathBuf::from(dir).join("envvars"));
+        }
+        dirs.push(temp_dir());
+    }
+    #[cfg(not(windows))]
+    {
+        if let Some(dir) = env::var_os("XDG_RUNTIME_DIR").filter(|v| !v.is_empty()) {
+            // The runtime folder is managed by the system and shouldn't be created
+            if Path::new(&dir).is_dir() {
+                dirs.push(PathBuf::from(dir).join("envvars"));
+            }
+        }
+        if let Some(dir) = env::var_os("XDG_CACHE_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home_dir().map(|home| home.join(".cache")))
+        {
+            dirs.push(dir.join("envvars"));
+        }
+        dirs.push(temp_dir().join(format!("envvars-{}", euid())));
+    }
+    dirs
+}
+
+#[cfg(not(windows))]
+fn euid() -> u32 {
+    unsafe { libc::geteuid() }
+}
+
+/// Returns all folders, where extractors could be created
+fn dirs(custom: Option<&PathBuf>) -> Vec<PathBuf> {
+    // Earlier versions created the extractor right in the temporary folder (see
+    // `is_legacy`)
+    let mut dirs = vec![temp_dir()];
+    dirs.extend(candidates(custom));
+    dirs
+}
+
+/// Creates the folder (if needed) and checks it can be used for the extractor: it
+/// belongs to the current user, cannot be changed by other users and its file
+/// system isn't mounted with `noexec`.
+#[cfg(not(windows))]
+fn prepare(dir: &Path) -> Result<(), io::Error> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+    DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    let metadata = dir.symlink_metadata()?;
+    if !metadata.is_dir() || metadata.uid() != euid() || metadata.mode() & 0o022 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "folder belongs to another user or can be changed by other users",
+        ));
+    }
+    let path = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if is_noexec(&path)? {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "file system is mounted with noexec",
+        ));
+    }
+    Ok(())
+}
+
+/// Checks the file system of the folder is mounted with `noexec`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn is_noexec(path: &std::ffi::CStr) -> Result<bool, io::Error> {
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stat.f_flag & libc::ST_NOEXEC != 0)
+}
+
+/// Checks the file system of the folder is mounted with `noexec`
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "openbsd"
+))]
+fn is_noexec(path: &std::ffi::CStr) -> Result<bool, io::Error> {
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_flags as u64 & libc::MNT_NOEXEC as u64 != 0)
+}
+
+/// Mount flags aren't available on other systems; if the folder is mounted with
+/// `noexec`, the extractor fails to start
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "openbsd"
+)))]
+fn is_noexec(_path: &std::ffi::CStr) -> Result<bool, io::Error> {
+    Ok(false)
+}
+
+#[cfg(windows)]
+fn prepare(dir: &Path) -> Result<(), io::Error> {
+    DirBuilder::new().recursive(true).create(dir)
+}
+
+/// Returns path of the extractor in the first suitable folder (see `candidates`)
+fn locate(custom: Option<&PathBuf>) -> Result<PathBuf, io::Error> {
+    for dir in candidates(custom) {
+        match prepare(&dir) {
+            Ok(()) => return Ok(dir.join(file_name())),
+            Err(err) => log::debug!("Folder {dir:?} cannot be used for extractor: {err}"),
+        }
+    }
+    Err(io::Error::other("no suitable folder for extractor"))
+}
+
+/// Additional options of executing the shell with extractor
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Additional environment variables for the shell
+    pub envs: Vec<(String, String)>,
+    /// Working folder of the shell. Current working folder will be used if not defined
+    pub cwd: Option<PathBuf>,
+    /// Commands, which are executed by the shell right before the extractor (in the
+    /// same script). Ignored if the extractor is started without shell
+    pub prelude: Option<String>,
+}
+
+impl Options {
+    pub(crate) fn apply<'a>(&self, command: &'a mut Command) -> &'a mut Command {
+        if let Some(cwd) = self.cwd.as_ref() {
+            command.current_dir(cwd);
+        }
+        command.envs(self.envs.iter().map(|(k, v)| (k, v)))
+    }
+}
+
+#[cfg(not(windows))]
+fn create_file(path: &Path) -> Result<File, io::Error> {
+    OpenOptions::new()
+        .mode(0o700)
+        .write(true)
+        .create_new(true)
+        .open(path)
+}
+
+#[cfg(windows)]
+fn create_file(path: &Path) -> Result<File, io::Error> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+#[cfg(not(windows))]
+fn open_file(path: &Path) -> Result<File, io::Error> {
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+}
+
+#[cfg(windows)]
+fn open_file(path: &Path) -> Result<File, io::Error> {
+    File::open(path)
+}
+
+/// Checks the opened file can be trusted: it's a regular file, which belongs to the
+/// current user and cannot be changed by other users
+#[cfg(not(windows))]
+fn is_trusted(file: &File) -> Result<bool, io::Error> {
+    let metadata = file.metadata()?;
+    Ok(metadata.is_file() && metadata.uid() == euid() && metadata.mode() & 0o022 == 0)
+}
+
+#[cfg(windows)]
+fn is_trusted(file: &File) -> Result<bool, io::Error> {
+    Ok(file.metadata()?.is_file())
+}
+
+/// Returns a unique path of temporary file next to the extractor
+fn temp_path(location: &Path) -> PathBuf {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&std::process::id().to_le_bytes());
+    hasher.update(
+        &SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default()
+            .to_le_bytes(),
+    );
+    let mut name = location.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", &hasher.finalize().to_hex()[..16]));
+    location.with_file_name(name)
+}
+
+/// Metadata of the verified extractor's file. The checksum is calculated again only
+/// if the metadata is changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stamp {
+    /// Device and inode (unix only)
+    id: (u64, u64),
+    size: u64,
+    modified: Option<SystemTime>,
+    /// Time of changing of inode (unix) or creating of the file (windows)
+    changed: Option<SystemTime>,
+}
+
+impl Stamp {
+    #[cfg(not(windows))]
+    fn new(metadata: &Metadata) -> Self {
+        Stamp {
+            id: (metadata.dev(), metadata.ino()),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            changed: u32::try_from(metadata.ctime_nsec()).ok().and_then(|nsec| {
+                UNIX_EPOCH.checked_add(Duration::new(metadata.ctime().try_into().ok()?, nsec))
+            }),
+        }
+    }
+
+    #[cfg(windows)]
+    fn new(metadata: &Metadata) -> Self {
+        Stamp {
+            id: (0, 0),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            changed: metadata.created().ok(),
+        }
+    }
+}
+
+/// Returns path of the lock file of the extractor
+fn lock_path(location: &Path) -> PathBuf {
+    let mut name = location.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    location.with_file_name(name)
+}
+
+/// Opens (creates if needed) the lock file of the extractor. The lock file is used
+/// to synchronize delivery and execution of the extractor between processes. It's
+/// never removed: a process, which opened the removed file, would hold a lock,
+/// which nobody else sees.
+fn lock_file(location: &Path) -> Result<File, io::Error> {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    #[cfg(not(windows))]
+    options.mode(0o600).custom_flags(libc::O_NOFOLLOW);
+    options.open(lock_path(location))
+}
+
+/// Checks the file is an extractor: it includes the signature of the extractor
+fn is_extractor(path: &Path) -> bool {
+    read(path)
+        .map(|bytes| bytes.windows(MAGIC.len()).any(|window| window == MAGIC))
+        .unwrap_or(false)
+}
+
+/// Removes the extractor (or its temporary file) if no process uses it. The lock
+/// file is kept (see `lock_file`).
+fn remove_unused(path: &Path, base: &Path) -> Result<bool, io::Error> {
+    let lock = lock_file(base)?;
+    if lock.try_lock().is_err() {
+        log::debug!("Extractor {path:?} is used by another process");
+        return Ok(false);
+    }
+    remove_file(path)?;
+    Ok(true)
+}
+
+/// Strings, which are compiled into extractors of earlier versions: the path of
+/// the source file and a message of `serde_json`, which was used to print variables
+const LEGACY_MARKERS: [&[u8]; 2] = [b"src/main.rs", b"key must be a string"];
+
+/// Max size of extractor of earlier versions
+const LEGACY_MAX_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Checks the file is an extractor of earlier versions. They were named with a bare
+/// UUID (v4) without `PREFIX` and didn't have the signature, so the content is
+/// checked: it should be a small executable (ELF, Mach-O or PE) with strings of the
+/// old extractor. On unix only executable files of the current user are considered.
+fn is_legacy(name: &str, path: &Path, metadata: &Metadata) -> bool {
+    #[cfg(windows)]
+    let Some(name) = name.strip_suffix(".exe") else {
+        return false;
+    };
+    #[cfg(not(windows))]
+    if metadata.uid() != euid() || metadata.mode() & 0o100 == 0 {
+        return false;
+    }
+    let is_uuid = name.len() == 36
+        && name.char_indices().all(|(pos, c)| match pos {
+            8 | 13 | 18 | 23 => c == '-',
+            14 => c == '4',
+            _ => c.is_ascii_digit() || ('a'..='f').contains(&c),
+        });
+    if !is_uuid || metadata.len() > LEGACY_MAX_SIZE {
+        return false;
+    }
+    let Ok(bytes) = read(path) else {
+        return false;
+    };
+    let executable = [
+        &b"\x7fELF"[..],
+        &[0xcf, 0xfa, 0xed, 0xfe],
+        &[0xce, 0xfa, 0xed, 0xfe],
+        &[0xca, 0xfe, 0xba, 0xbe],
+        b"MZ",
+    ]
+    .iter()
+    .any(|header| bytes.starts_with(header));
+    executable
+        && LEGACY_MARKERS
+            .iter()
+            .all(|marker| bytes.windows(marker.len()).any(|window| window == *marker))
+}
+
+/// Checks the name is a name of temporary file of the extractor:
+/// "{PREFIX}{name}.{16 hex}.tmp" (see `temp_path`). Returns the name of the
+/// extractor.
+fn temp_base(name: &str) -> Option<&str> {
+    let (base, random) = name.strip_suffix(".tmp")?.rsplit_once('.')?;
+    (base.starts_with(PREFIX)
+        && random.len() == 16
+        && random.chars().all(|c| c.is_ascii_hexdigit()))
+    .then_some(base)
+}
+
+/// Removes extractors of `envvars` from the folders, which aren't used by any
+/// process. Only files with `PREFIX` and the signature of the extractor are
+/// removed; temporary files without the signature (left by a failed delivery)
+/// are removed only if `all` is `true`. Lock files are kept (see `lock_file`).
+/// Returns the number of removed files.
+///
+/// * `dirs` - folders to check
+/// * `legacy` - folder, where earlier versions created extractors, if they should
+///   be removed as well (see `is_legacy`)
+/// * `all` - if `false`, the extractor of the current build is kept
+fn collect(dirs: &[PathBuf], legacy: Option<&Path>, all: bool) -> usize {
+    let current = file_name();
+    let mut removed = 0;
+    for dir in dirs {
+        let Ok(entries) = read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file() || name.ends_with(".lock") {
+                continue;
+            }
+            let path = entry.path();
+            if legacy == Some(dir.as_path()) && is_legacy(&name, &path, &metadata) {
+                // Earlier versions didn't lock the extractor
+                match remove_file(&path) {
+                    Ok(()) => {
+                        log::debug!("Extractor of earlier version {path:?} is removed");
+                        removed += 1;
+                    }
+                    Err(err) => log::debug!("Fail to remove extractor {path:?}: {err}"),
+                }
+                continue;
+            }
+            if !name.starts_with(PREFIX) {
+                continue;
+            }
+            let tmp = temp_base(&name);
+            let base = tmp.unwrap_or(&name);
+            // Temporary file can be left without signature by a failed delivery
+            let verified = (all && tmp.is_some()) || is_extractor(&path);
+            if (!all && base == current) || !verified {
+                continue;
+            }
+            match remove_unused(&path, &dir.join(base)) {
+                Ok(true) => {
+                    log::debug!("Extractor {path:?} is removed");
+                    removed += 1;
+                }
+                Ok(false) => {}
+                Err(err) => log::debug!("Fail to remove extractor {path:?}: {err}"),
+            }
+        }
+    }
+    removed
+}
+
+/// Creates an anonymous file in memory with the extractor and seals it, so the
+/// content cannot be changed anymore. Returns the file reopened in read-only mode:
+/// a file cannot be executed while it's opened for writing.
+#[cfg(target_os = "linux")]
+fn memfd() -> Result<File, io::Error> {
+    use std::os::fd::FromRawFd;
+    let create = |flags| unsafe { libc::memfd_create(c"envvars".as_ptr(), flags) };
+    let flags = libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING;
+    // MFD_EXEC is required if vm.memfd_noexec is set, but older kernels reject it
+    let mut fd = create(flags | libc::MFD_EXEC);
+    if fd < 0 && io::Error::last_os_error().raw_os_error() == Some(libc::EINVAL) {
+        fd = create(flags);
+    }
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(assets::bin())?;
+    let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+    if unsafe { libc::fcntl(fd, libc::F_ADD_SEALS, seals) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    File::open(format!("/proc/self/fd/{fd}"))
 }
 
 pub struct Extractor {
-    location: PathBuf,
+    /// Folder defined with `set_extractor_dir`
+    dir: Option<PathBuf>,
+    /// Path of the extractor's file. It's defined on first writing of the file.
+    pub(crate) location: Option<PathBuf>,
+    /// Opened executable file of the extractor, which checksum is verified. The
+    /// extractor is executed through this descriptor where it's possible.
+    verified: Option<File>,
+    /// `true` if `verified` is a sealed file in memory (Linux only)
+    sealed: bool,
+    /// Metadata of the file of `verified` at the moment of verification
+    stamp: Option<Stamp>,
+    /// `true` if extractors of other builds were already removed
+    collected: bool,
+    /// Allows to load the extractor into memory instead of writing it into the
+    /// temporary folder (Linux only). Field is used for testing to force the
+    /// file-based extractor.
+    pub(crate) memfd: bool,
     /// Field is used only for testing to confirm status of hash checking
     pub(crate) invalid_hash: bool,
 }
@@ -35,88 +501,278 @@ pub struct Extractor {
 impl Extractor {
     pub fn new() -> Self {
         Extractor {
-            location: get_extractor_path(),
+            dir: None,
+            location: None,
+            verified: None,
+            sealed: false,
+            stamp: None,
+            collected: false,
+            memfd: true,
             invalid_hash: false,
         }
     }
 
-    #[cfg(not(windows))]
-    fn create_file(&self) -> Result<File, io::Error> {
-        OpenOptions::new()
-            .mode(0o777)
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&self.location)
+    /// Sets the folder of the extractor. If `None`, the folder is selected
+    /// automatically. See `set_extractor_dir`.
+    pub(crate) fn set_dir(&mut self, dir: Option<PathBuf>) {
+        self.dir = dir;
+        self.location = None;
+        if !self.sealed {
+            self.verified = None;
+            self.stamp = None;
+        }
     }
-    #[cfg(windows)]
-    fn create_file(&self) -> Result<File, io::Error> {
-        OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&self.location)
+
+    /// Forgets the verified state, so the extractor is verified (and delivered if
+    /// needed) again on the next use
+    fn reset(&mut self) {
+        self.verified = None;
+        self.stamp = None;
+        self.sealed = false;
+    }
+
+    /// Removes the file of the extractor (if it was created by the instance) if no
+    /// process uses it
+    pub(crate) fn remove(&mut self) -> Result<(), io::Error> {
+        self.reset();
+        if let Some(location) = self.location.take() {
+            if location.exists() {
+                remove_unused(&location, &location)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the verified file is still in the location and isn't changed since
+    /// verification. Both the file in the location and the opened descriptor are
+    /// checked.
+    fn is_unchanged(&self, location: &Path) -> bool {
+        let (Some(file), Some(stamp)) = (self.verified.as_ref(), self.stamp.as_ref()) else {
+            return false;
+        };
+        let (Ok(opened), Ok(current)) = (file.metadata(), location.symlink_metadata()) else {
+            return false;
+        };
+        current.is_file() && Stamp::new(&opened) == *stamp && Stamp::new(&current) == *stamp
     }
 
-    fn delivery(&mut self) -> Result<(), io::Error> {
-        if self.location.exists() {
+    /// Saves the verified file and its metadata
+    fn accept(&mut self, file: File) -> Result<(), io::Error> {
+        self.stamp = Some(Stamp::new(&file.metadata()?));
+        self.verified = Some(file);
+        Ok(())
+    }
+
+    /// Opens the extractor and verifies its checksum. Returns `None` if the file
+    /// doesn't exist, cannot be trusted or has invalid checksum.
+    fn verify(&mut self, location: &Path) -> Result<Option<File>, io::Error> {
+        let mut file = match open_file(location) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                log::warn!("Fail to open extractor {:?}: {err}", location);
+                return Ok(None);
+            }
+        };
+        if !is_trusted(&file)? {
             log::warn!(
-                "Extractor {:?} already exists. Checking checksum.",
-                self.location
+                "Extractor {:?} belongs to another user or can be changed by other users",
+                location
             );
-            if !match checksum(&self.location) {
-                Ok(checksum) => checksum == assets::checksum(),
-                Err(err) => {
-                    log::warn!(
-                        "Fail to get checksum of extractor {:?}: {err}",
-                        self.location
-                    );
-                    self.invalid_hash = true;
-                    false
+            return Ok(None);
+        }
+        match checksum_of(&mut file) {
+            Ok(checksum) if checksum == assets::checksum() => Ok(Some(file)),
+            Ok(_) => {
+                log::warn!("Extractor {:?} has invalid checksum", location);
+                Ok(None)
+            }
+            Err(err) => {
+                log::warn!("Fail to get checksum of extractor {:?}: {err}", location);
+                self.invalid_hash = true;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Writes the extractor into a new file with a random name (the file must not
+    /// exist) and moves it into the location atomically
+    fn write(location: &Path) -> Result<(), io::Error> {
+        let tmp = temp_path(location);
+        let result = create_file(&tmp).and_then(|mut file| {
+            file.write_all(assets::bin())?;
+            file.sync_all()?;
+            drop(file);
+            rename(&tmp, location)
+        });
+        if result.is_err() {
+            let _ = remove_file(&tmp);
+        }
+        result
+    }
+
+    /// Loads the extractor into sealed memory. Returns `false` if it isn't
+    /// possible and the extractor should be written into the temporary folder.
+    #[cfg(target_os = "linux")]
+    fn delivery_memfd(&mut self) -> bool {
+        if !self.memfd {
+            return false;
+        }
+        if self.sealed && self.verified.is_some() {
+            return true;
+        }
+        match memfd() {
+            Ok(file) => {
+                self.verified = Some(file);
+                self.sealed = true;
+                if self.fd_path().is_some() {
+                    return true;
                 }
-            } {
-                remove_file(&self.location)?;
-            } else {
-                return Ok(());
+                log::debug!("/proc isn't available; extractor is written into file");
+            }
+            Err(err) => {
+                log::debug!(
+                    "Fail to load extractor into memory: {err}; extractor is written into file"
+                );
             }
         }
-        let mut file = self.create_file()?;
-        file.write_all(assets::bin())?;
-        file.flush()?;
-        log::debug!("File is written in: {:?}", self.location);
-        Ok(())
+        self.memfd = false;
+        false
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn delivery_memfd(&mut self) -> bool {
+        false
+    }
+
+    /// Delivers the extractor. If the extractor is a file, returns the lock file
+    /// with shared lock, which should be kept until the extractor is executed.
+    /// Checking and writing of the file happen under exclusive lock, so other
+    /// processes never see the file partly written or removed.
+    fn delivery(&mut self) -> Result<Option<File>, io::Error> {
+        if !self.collected {
+            self.collected = true;
+            collect(&dirs(self.dir.as_ref()), None, false);
+        }
+        if self.delivery_memfd() {
+            return Ok(None);
+        }
+        let location = match self.location.clone() {
+            Some(location) => location,
+            None => {
+                let location = locate(self.dir.as_ref())?;
+                self.location = Some(location.clone());
+                location
+            }
+        };
+        let lock = lock_file(&location)?;
+        lock.lock_shared()?;
+        if !self.sealed && self.is_unchanged(&location) {
+            return Ok(Some(lock));
+        }
+        self.verified = None;
+        self.stamp = None;
+        self.sealed = false;
+        if let Some(file) = self.verify(&location)? {
+            self.accept(file)?;
+            return Ok(Some(lock));
+        }
+        lock.unlock()?;
+        lock.lock()?;
+        // Another process could deliver the extractor while waiting for the lock
+        let file = match self.verify(&location)? {
+            Some(file) => file,
+            None => {
+                Self::write(&location)?;
+                log::debug!("File is written in: {location:?}");
+                self.verify(&location)?.ok_or(io::Error::other(format!(
+                    "Fail to verify extractor {location:?}"
+                )))?
+            }
+        };
+        self.accept(file)?;
+        lock.unlock()?;
+        lock.lock_shared()?;
+        Ok(Some(lock))
+    }
+
+    /// Returns path to execute the extractor. On Linux the extractor is executed
+    /// through the verified descriptor (a file or a sealed file in memory), so the
+    /// file cannot be replaced between verifying and executing.
+    pub(crate) fn executable(&self) -> PathBuf {
+        #[cfg(target_os = "linux")]
+        if let Some(path) = self.fd_path() {
+            return path;
+        }
+        self.location.clone().unwrap_or_default()
+    }
+
+    /// Returns path of the verified descriptor in `/proc` if it's available
+    #[cfg(target_os = "linux")]
+    fn fd_path(&self) -> Option<PathBuf> {
+        let file = self.verified.as_ref()?;
+        let path = PathBuf::from(format!(
+            "/proc/{}/fd/{}",
+            std::process::id(),
+            file.as_raw_fd()
+        ));
+        path.exists().then_some(path)
     }
 
     #[cfg(not(windows))]
-    fn output(&self, shell: Option<&PathBuf>, args: &[String]) -> Result<Output, io::Error> {
+    fn command(&self, shell: Option<&PathBuf>, args: &[String], prelude: Option<&str>) -> Command {
         if let Some(shell) = shell {
-            Command::new(shell)
-                .args(args.iter())
-                .arg(&self.location)
-                .output()
+            let mut command = Command::new(shell);
+            command.args(args.iter());
+            if let Some(prelude) = prelude {
+                let executable = self.executable().to_string_lossy().replace('\'', "'\\''");
+                command.arg(format!("{prelude}\n'{executable}'"));
+            } else {
+                command.arg(self.executable());
+            }
+            command
         } else {
-            Command::new(&self.location).output()
+            Command::new(self.executable())
         }
     }
 
     #[cfg(windows)]
-    fn output(&self, shell: Option<&PathBuf>, args: &[String]) -> Result<Output, io::Error> {
-        if let Some(shell) = shell {
-            const CREATE_NO_WINDOW: u32 = 0x08000000;
-            Command::new(shell)
-                .args(args.iter())
-                .arg(
-                    &self
-                        .location
-                        .to_string_lossy()
-                        .to_string()
-                        .replace('\\', "\\\\"),
-                )
-                .creation_flags(CREATE_NO_WINDOW)
-                .output()
+    fn command(&self, shell: Option<&PathBuf>, args: &[String], prelude: Option<&str>) -> Command {
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        let mut command = if let Some(shell) = shell {
+            let mut command = Command::new(shell);
+            let executable = self
+                .executable()
+                .to_string_lossy()
+                .to_string()
+                .replace('\\', "\\\\");
+            command.args(args.iter()).arg(match prelude {
+                Some(prelude) => format!("{prelude}\n{executable}"),
+                None => executable,
+            });
+            command
         } else {
-            Command::new(&self.location).output()
-        }
+            Command::new(self.executable())
+        };
+        command.creation_flags(CREATE_NO_WINDOW);
+        command
+    }
+
+    /// Runs the shell with the extractor. Returns output and pid of the shell.
+    fn output(
+        &self,
+        shell: Option<&PathBuf>,
+        args: &[String],
+        options: &Options,
+    ) -> Result<(Output, u32), io::Error> {
+        let child = options
+            .apply(&mut self.command(shell, args, options.prelude.as_deref()))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let pid = child.id();
+        Ok((child.wait_with_output()?, pid))
     }
 
     pub fn get(
@@ -124,18 +780,69 @@ impl Extractor {
         shell: Option<&PathBuf>,
         args: &[String],
     ) -> Result<HashMap<String, String>, Error> {
-        self.delivery().map_err(Error::Create)?;
-        let output = self.output(shell, args).map_err(Error::Executing)?;
-        let stdout = from_utf8(&output.stdout).map_err(Error::Decoding)?;
+        self.get_with(shell, args, &Options::default())
+            .map(|(envvars, _)| envvars)
+    }
+
+    /// Same as `get`, but allows to define additional options of executing the
+    /// shell. Returns also stderr of the shell.
+    pub fn get_with(
+        &mut self,
+        shell: Option<&PathBuf>,
+        args: &[String],
+        options: &Options,
+    ) -> Result<(HashMap<String, String>, String), Error> {
+        self.request(shell, args, options, false)
+            .map(|(envelope, stderr)| (envelope.payload.envvars, stderr))
+    }
+
+    /// Same as `get_with`, but the extractor reports also the context of the
+    /// process (working folder, umask, limits, locale).
+    pub fn get_with_context(
+        &mut self,
+        shell: Option<&PathBuf>,
+        args: &[String],
+        options: &Options,
+    ) -> Result<(HashMap<String, String>, ShellContext, String), Error> {
+        let (envelope, stderr) = self.request(shell, args, options, true)?;
+        let context = envelope.payload.context.ok_or_else(|| Error::Protocol {
+            message: format!(
+                "context isn't reported by extractor (protocol {})",
+                envelope.protocol
+            ),
+            diagnostics: Box::new(Diagnostics::new(shell, args).with_stderr(&stderr)),
+        })?;
+        Ok((envelope.payload.envvars, context, stderr))
+    }
+
+    /// Runs the extractor and validates its envelope (see `protocol::Request`)
+    fn request(
+        &mut self,
+        shell: Option<&PathBuf>,
+        args: &[String],
+        options: &Options,
+        context: bool,
+    ) -> Result<(Envelope, String), Error> {
+        // Lock is released after the extractor is executed
+        let _lock = self.delivery().map_err(|source| Error::Create {
+            source,
+            location: self.location.clone(),
+        })?;
+        let mut options = options.clone();
+        let request = Request::new(&mut options, context);
+        let started = Instant::now();
+        let (output, pid) = self.output(shell, args, &options).map_err(|source| {
+            Error::Executing {
+                source,
+                diagnostics: Box::default(),
+            }
+            .with_command(shell, args, started.elapsed())
+        })?;
+        let envelope = request
+            .parse(&output, pid)
+            .map_err(|err| err.with_command(shell, args, started.elapsed()))?;
         let stderr = from_utf8(&output.stderr).map_err(Error::Decoding)?;
-        serde_json::from_str::<HashMap<String, String>>(stdout).map_err(|e| {
-            Error::Parsing(
-                e,
-                output.status.code(),
-                stdout.to_owned(),
-                stderr.to_owned(),
-            )
-        })
+        Ok((envelope, stderr.to_owned()))
     }
 }
 
@@ -145,28 +852,77 @@ impl Default for Extractor {
     }
 }
 
+impl Drop for Extractor {
+    /// Removes the file of the extractor (if it was created), if no process uses it.
+    /// The default extractor is never dropped.
+    fn drop(&mut self) {
+        if let Err(err) = self.remove() {
+            log::warn!("Fail to remove extractor: {err}");
+        }
+    }
+}
+
 /// Removes extractor file from OS temporary folder. `envvars` creates a small
-/// executable file in OS temporary folder. This application drops a list of
-/// available environment variables and does nothing else. As soon as the
-/// extractor has been created, `envvars` uses it. But it still can be safely
-/// removed for cleaning purposes for example before closing of an application.
+/// executable file in a private folder of the user (see `set_extractor_dir`). This
+/// application drops a list of available environment variables and does nothing
+/// else. As soon as the extractor has been created, `envvars` uses it. But it still
+/// can be safely removed for cleaning purposes for example before closing of an
+/// application.
 ///
 /// If `envvars` doesn't detect an extractor, it will be created again.
 ///
-/// Note, `envvars` doesn't remove an extractor application automatically.
+/// Note, `envvars` doesn't remove an extractor application automatically. On Linux
+/// the extractor is loaded into memory (`memfd_create`) and the file is created
+/// only if it isn't possible. Extractors, which are executed by other processes
+/// right now, are kept.
 pub fn cleanup() -> Result<(), io::Error> {
-    let path = get_extractor_path();
-    if !path.exists() {
-        Ok(())
-    } else {
-        remove_file(&path)
+    let custom = lock(&EXTRACTOR).dir.clone();
+    remove_current(&dirs(custom.as_ref()))
+}
+
+/// Removes the extractor of the current build from the folders, if no process uses it
+fn remove_current(dirs: &[PathBuf]) -> Result<(), io::Error> {
+    for dir in dirs {
+        let path = dir.join(file_name());
+        if path.exists() {
+            remove_unused(&path, &path)?;
+        }
     }
+    Ok(())
+}
+
+/// Removes extractors of all builds of `envvars` (including the current one) from
+/// the folders of extractors, if they aren't used by any process right now.
+/// Returns the number of removed files.
+///
+/// `envvars` removes extractors of other builds automatically on the first
+/// extracting. This function is useful for cleaning purposes, for example before
+/// uninstalling of an application. Unlike the automatic cleanup, it also removes
+/// extractors of earlier versions, which were named with a bare UUID and created
+/// right in the temporary folder; their content is checked before removing.
+pub fn cleanup_all() -> Result<usize, io::Error> {
+    let custom = lock(&EXTRACTOR).dir.clone();
+    Ok(collect(&dirs(custom.as_ref()), Some(&temp_dir()), true))
+}
+
+/// Locks the extractor. If a thread panicked while holding the lock, the state of
+/// the extractor can be inconsistent: the verified state is reset, so the
+/// extractor is verified and delivered again, and the lock is used as usual.
+pub(crate) fn lock(extractor: &Mutex<Extractor>) -> MutexGuard<'_, Extractor> {
+    extractor.lock().unwrap_or_else(|poisoned| {
+        log::warn!("Extractor was locked by a panicked thread; state of extractor is reset");
+        let mut guard = poisoned.into_inner();
+        guard.reset();
+        extractor.clear_poison();
+        guard
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{profiles::get as get_profiles, Profile, EXTRACTOR};
+    use crate::{profiles::get as get_profiles, testing::TempDir, Profile};
+    use std::sync::Arc;
 
     fn extract() -> Result<(), Error> {
         let mut profiles = get_profiles()?;
@@ -196,10 +952,10 @@ mod tests {
         println!("Found shells with failed detection of envvars:");
         println!("{}", "=".repeat(50));
         failed.iter().for_each(|(p, err)| match err {
-            Error::Parsing(_err, code, stdout, stderr) => {
-                println!("{}: {:?}; code: {code:?}", p.name, p.path,);
-                println!("{}: {:?}; stdout:\n{stdout}", p.name, p.path,);
-                println!("{}: {:?}; stderr:\n{stderr}", p.name, p.path,);
+            Error::Parsing { diagnostics, .. } => {
+                println!("{}: {:?}; code: {:?}", p.name, p.path, diagnostics.code);
+                println!("{}: {:?}; stdout:\n{}", p.name, p.path, diagnostics.stdout);
+                println!("{}: {:?}; stderr:\n{}", p.name, p.path, diagnostics.stderr);
             }
             _ => {
                 println!("{}: {:?}; fail to get envvars: {err}", p.name, p.path,);
@@ -209,10 +965,17 @@ mod tests {
     }
     #[test]
     fn test() {
+        // Force the file-based extractor
+        EXTRACTOR.lock().expect("Access to extractor").memfd = false;
         // Extracting
         extract().expect("Envvars should be extracted");
         // Remove extractor
-        let extractor_path = get_extractor_path();
+        let extractor_path = EXTRACTOR
+            .lock()
+            .expect("Access to extractor")
+            .location
+            .clone()
+            .expect("Extractor should be written into file");
         remove_file(&extractor_path).expect("Extractor should removed");
         // Extracting again
         extract().expect("Envvars should be extracted");
@@ -230,5 +993,252 @@ mod tests {
         // Extractor should detect changes on executable file with invalid hash and rewrite
         // executable file again
         assert!(!EXTRACTOR.lock().expect("Access to extractor").invalid_hash);
+        // Verified state is cached, but changes of the file still are detected
+        assert_eq!(
+            crate::checksum::checksum(&extractor_path).expect("Checksum should be calculated"),
+            assets::checksum()
+        );
+    }
+
+    /// Helper, which holds shared lock of the extractor (like a process, which
+    /// executes it) until stdin is closed
+    #[test]
+    #[ignore = "started by `locked_by_another_process`"]
+    fn hold_lock() {
+        use std::io::BufRead;
+        let Some(path) = env::var_os("ENVVARS_TEST_LOCK") else {
+            return;
+        };
+        let lock = lock_file(Path::new(&path)).expect("Lock file should be opened");
+        lock.lock_shared().expect("Lock should be taken");
+        println!("locked");
+        io::stdout().flush().expect("Stdout should be flushed");
+        let _ = io::stdin().lock().read_line(&mut String::new());
+    }
+
+    #[test]
+    fn locked_by_another_process() {
+        use std::io::{BufRead, BufReader};
+        let temp = TempDir::new("extractor-lock");
+        let dir = temp.path().join("extractor");
+        prepare(&dir).expect("Folder should be prepared");
+        let path = dir.join(file_name());
+        Extractor::write(&path).expect("Extractor should be written");
+        let mut holder = Command::new(env::current_exe().expect("Path to tests"))
+            .args(["extractor::tests::hold_lock", "--exact", "--ignored"])
+            .args(["--nocapture", "--test-threads=1"])
+            .env("ENVVARS_TEST_LOCK", &path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Holder should be started");
+        let stdout = holder.stdout.take().expect("Stdout of holder");
+        let mut lines = BufReader::new(stdout).lines().map_while(Result::ok);
+        let locked = lines.any(|line| line.contains("locked"));
+        assert!(locked, "Holder should take the lock");
+        // Used extractor is kept by both cleanup and garbage collection
+        remove_current(std::slice::from_ref(&dir)).expect("Cleanup should be done");
+        assert!(path.exists());
+        assert!(!remove_unused(&path, &path).expect("Lock should be checked"));
+        assert!(path.exists());
+        drop(holder.stdin.take());
+        lines.for_each(drop);
+        assert!(holder.wait().expect("Holder should be finished").success());
+        remove_current(std::slice::from_ref(&dir)).expect("Cleanup should be done");
+        assert!(!path.exists());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn collect_stale() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp = TempDir::new("extractor-collect");
+        let dir = temp.path().join("extractor");
+        prepare(&dir).expect("Folder should be prepared");
+        let create = |name: &str, content: &[u8], mode: u32| {
+            let path = dir.join(name);
+            std::fs::write(&path, content).expect("File should be written");
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))
+                .expect("Permissions should be set");
+            path
+        };
+        let current = create(&file_name(), MAGIC, 0o700);
+        let other = create(&format!("{PREFIX}other"), MAGIC, 0o700);
+        let tmp = create(&format!("{PREFIX}other.0123456789abcdef.tmp"), MAGIC, 0o600);
+        let broken = create(&format!("{PREFIX}other.fedcba9876543210.tmp"), b"", 0o600);
+        let old = b"\x7fELF..src/main.rs..key must be a string..";
+        let legacy = create("0f8e4a6c-1b2d-4c3e-9f5a-6b7c8d9e0a1b", old, 0o700);
+        // Lock file, not executable, not a UUID v4, a foreign binary, not an
+        // extractor and a foreign file
+        let kept = [
+            create(&format!("{PREFIX}gone.lock"), b"", 0o600),
+            create("0f8e4a6c-1b2d-4c3e-9f5a-6b7c8d9e0a1c", old, 0o600),
+            create("0f8e4a6c-1b2d-1c3e-9f5a-6b7c8d9e0a1b", old, 0o700),
+            create("0f8e4a6c-1b2d-4c3e-9f5a-6b7c8d9e0a1d", b"\x7fELF", 0o700),
+            create(&format!("{PREFIX}fake"), b"", 0o700),
+            create("notes.txt", b"", 0o600),
+        ];
+        // Automatic cleanup removes only verified extractors of other builds
+        assert_eq!(collect(std::slice::from_ref(&dir), None, false), 2);
+        assert!(!other.exists() && !tmp.exists());
+        assert!(current.exists() && broken.exists() && legacy.exists());
+        assert!(kept.iter().all(|path| path.exists()));
+        // Explicit cleanup removes everything of envvars including earlier versions
+        assert_eq!(collect(std::slice::from_ref(&dir), Some(&dir), true), 3);
+        for path in [&current, &broken, &legacy] {
+            assert!(!path.exists(), "{path:?} should be removed");
+        }
+        assert!(kept.iter().all(|path| path.exists()));
+        assert!(lock_path(&current).exists());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn private_folder() {
+        let temp = TempDir::new("extractor-private");
+        let dir = temp.path().join("extractor");
+        prepare(&dir).expect("Folder should be prepared");
+        let metadata = dir.symlink_metadata().expect("Metadata of folder");
+        assert_eq!(metadata.mode() & 0o777, 0o700);
+        assert_eq!(metadata.uid(), euid());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn unsafe_folders() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+        let temp = TempDir::new("extractor-unsafe");
+        let dir = temp.path().join("extractor");
+        // Folder, which can be changed by other users
+        let writable = dir.join("writable");
+        std::fs::create_dir_all(&writable).expect("Folder should be created");
+        std::fs::set_permissions(&writable, std::fs::Permissions::from_mode(0o777))
+            .expect("Permissions should be set");
+        let err = prepare(&writable).expect_err("World-writable folder should be rejected");
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        // Symlink to a private folder
+        let private = dir.join("private");
+        prepare(&private).expect("Folder should be prepared");
+        let link = dir.join("link");
+        symlink(&private, &link).expect("Symlink should be created");
+        assert!(prepare(&link).is_err());
+        // Folder of another user
+        let foreign = if euid() == 0 {
+            let foreign = dir.join("foreign");
+            prepare(&foreign).expect("Folder should be prepared");
+            let path = std::ffi::CString::new(foreign.as_os_str().as_encoded_bytes())
+                .expect("Path without NUL");
+            assert_eq!(unsafe { libc::chown(path.as_ptr(), 65534, 65534) }, 0);
+            foreign
+        } else {
+            PathBuf::from("/")
+        };
+        let err = prepare(&foreign).expect_err("Folder of another user should be rejected");
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        // Unsuitable folder is skipped
+        let location = locate(Some(&writable)).expect("Another folder should be found");
+        assert_ne!(location.parent(), Some(writable.as_path()));
+    }
+
+    /// Mount flags are compared with `/proc/mounts`
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn noexec_mounts() {
+        let mounts = std::fs::read_to_string("/proc/mounts").expect("Mounts should be read");
+        // The last mount on the same point hides previous ones
+        let mut points: HashMap<&str, bool> = HashMap::new();
+        for line in mounts.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if let (Some(point), Some(flags)) = (fields.get(1), fields.get(3)) {
+                points.insert(point, flags.split(',').any(|flag| flag == "noexec"));
+            }
+        }
+        for (point, noexec) in points.into_iter().filter(|(p, _)| !p.contains('\\')) {
+            let path = std::ffi::CString::new(point).expect("Path without NUL");
+            if let Ok(detected) = is_noexec(&path) {
+                assert_eq!(detected, noexec, "Mount flags of {point}");
+            }
+        }
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn exclusive_create() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp = TempDir::new("extractor-exclusive");
+        let dir = temp.path().join("extractor");
+        prepare(&dir).expect("Folder should be prepared");
+        let path = dir.join(file_name());
+        drop(create_file(&path).expect("File should be created"));
+        let metadata = path.symlink_metadata().expect("Metadata of file");
+        assert_eq!(metadata.mode() & 0o777, 0o700);
+        // Existing file (or a file prepared by somebody else) is never reused
+        let err = create_file(&path).expect_err("Existing file should be refused");
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(
+            is_trusted(&open_file(&path).expect("File should be opened"))
+                .expect("File should be checked")
+        );
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o766))
+            .expect("Permissions should be set");
+        assert!(
+            !is_trusted(&open_file(&path).expect("File should be opened"))
+                .expect("File should be checked")
+        );
+        // Symlinks aren't followed
+        let link = dir.join("link");
+        std::os::unix::fs::symlink(&path, &link).expect("Symlink should be created");
+        assert!(open_file(&link).is_err());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn locking() {
+        let temp = TempDir::new("extractor-locking");
+        let dir = temp.path().join("extractor");
+        prepare(&dir).expect("Folder should be prepared");
+        let path = dir.join(file_name());
+        Extractor::write(&path).expect("Extractor should be written");
+        // Processes, which execute the extractor, share the lock
+        let executing = lock_file(&path).expect("Lock file should be opened");
+        executing.lock_shared().expect("Lock should be taken");
+        let another = lock_file(&path).expect("Lock file should be opened");
+        another
+            .try_lock_shared()
+            .expect("Shared lock should be taken");
+        another.unlock().expect("Lock should be released");
+        // Delivery and removing need the exclusive lock
+        let exclusive = lock_file(&path).expect("Lock file should be opened");
+        assert!(exclusive.try_lock().is_err());
+        assert!(!remove_unused(&path, &path).expect("Lock should be checked"));
+        assert!(path.exists());
+        drop(executing);
+        exclusive
+            .try_lock()
+            .expect("Exclusive lock should be taken");
+        exclusive.unlock().expect("Lock should be released");
+        assert!(remove_unused(&path, &path).expect("Lock should be checked"));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn poisoned() {
+        let extractor = Arc::new(Mutex::new(Extractor::new()));
+        lock(&extractor)
+            .get(None, &[])
+            .expect("Envvars should be extracted");
+        let shared = extractor.clone();
+        std::thread::spawn(move || {
+            let _guard = shared.lock();
+            panic!("Panic while the extractor is locked");
+        })
+        .join()
+        .expect_err("Thread should panic");
+        assert!(extractor.is_poisoned());
+        // State is reset and the extractor is delivered again
+        lock(&extractor)
+            .get(None, &[])
+            .expect("Envvars should be extracted after panic");
+        assert!(!extractor.is_poisoned());
     }
 }
@@ -0,0 +1,259 @@
+use crate::{
+    error::Diagnostics,
+    extractor::Options,
+    shellvars::{after_marker, run, MARKER},
+    syntax::words,
+    Error, ShellKind,
+};
+use serde::Deserialize;
+use std::{collections::HashMap, path::PathBuf};
+
+/// Prefix of environment variables with exported functions of bash
+const FUNC_PREFIX: &str = "BASH_FUNC_";
+
+/// Returns name of function if the variable is an exported function of bash:
+/// `BASH_FUNC_name%%` (bash 4.3+) or `BASH_FUNC_name()` (patched bash 4.2 and older)
+fn function_name(key: &str) -> Option<&str> {
+    let name = key.strip_prefix(FUNC_PREFIX)?;
+    name.strip_suffix("%%").or(name.strip_suffix("()"))
+}
+
+/// Separates exported functions of bash from environment variables. Returns
+/// environment variables without functions and functions with decoded bodies
+/// (like `{ echo "hello"\n}`).
+pub(crate) fn split(
+    envvars: HashMap<String, String>,
+) -> (HashMap<String, String>, HashMap<String, String>) {
+    let mut functions: HashMap<String, String> = HashMap::new();
+    let envvars = envvars
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let body = function_name(&key)
+                .and_then(|name| value.strip_prefix("()").map(|body| (name, body)));
+            if let Some((name, body)) = body {
+                functions.insert(name.to_owned(), body.trim_start().to_owned());
+                None
+            } else {
+                Some((key, value))
+            }
+        })
+        .collect();
+    (envvars, functions)
+}
+
+/// Encodes functions into environment variables in the format of bash 4.3+
+pub(crate) fn encode(functions: &HashMap<String, String>) -> HashMap<String, String> {
+    functions
+        .iter()
+        .map(|(name, body)| (format!("{FUNC_PREFIX}{name}%%"), format!("() {body}")))
+        .collect()
+}
+
+/// Returns a command, which prints aliases of the shell
+fn script(kind: ShellKind) -> Result<String, Error> {
+    Ok(match kind {
+        ShellKind::Bash | ShellKind::Zsh | ShellKind::Sh | ShellKind::Ksh => {
+            format!("printf '%s\\n' '{MARKER}'; alias")
+        }
+        ShellKind::Fish => format!("echo '{MARKER}'; alias; abbr --show"),
+        ShellKind::Csh => format!("echo '{MARKER}'; alias"),
+        ShellKind::PowerShell => format!(
+            "Write-Output '{MARKER}'; Get-Alias | Select-Object Name, Definition \
+             | ConvertTo-Json -Compress"
+        ),
+        ShellKind::Nu => {
+            format!("print '{MARKER}'; scope aliases | select name expansion | to json --raw")
+        }
+        _ => return Err(Error::NotSupportedShell(format!("{kind:?}"))),
+    })
+}
+
+/// Parses output of `alias`: `alias name='value'` (bash) or `name='value'` (zsh,
+/// sh, ksh)
+fn parse_posix(output: &str) -> HashMap<String, String> {
+    words(output)
+        .into_iter()
+        .filter(|word| word != "alias")
+        .filter_map(|word| {
+            word.split_once('=')
+                .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        })
+        .collect()
+}
+
+/// Parses output of `alias` (`alias name 'value'`) and `abbr --show`
+/// (`abbr -a -- name 'value'`) of fish
+fn parse_fish(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let words = words(line);
+            let start = match words.first().map(|w| w.as_str()) {
+                Some("alias") => 1,
+                Some("abbr") => match words.iter().position(|w| w == "--") {
+                    Some(pos) => pos + 1,
+                    None => {
+                        words
+                            .iter()
+                            .skip(1)
+                            .take_while(|w| w.starts_with('-'))
+                            .count()
+                            + 1
+                    }
+                },
+                _ => return None,
+            };
+            let name = words.get(start)?;
+            Some((name.to_owned(), words[start + 1..].join(" ")))
+        })
+        .collect()
+}
+
+/// Parses output of `alias` of csh: `name<TAB>value`
+fn parse_csh(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once('\t')?;
+            let value = value
+                .strip_prefix('(')
+                .and_then(|v| v.strip_suffix(')'))
+                .unwrap_or(value);
+            Some((name.to_owned(), value.to_owned()))
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct Alias {
+    #[serde(alias = "Name")]
+    name: String,
+    #[serde(alias = "Definition", alias = "expansion")]
+    value: Option<String>,
+}
+
+/// Parses JSON output of `Get-Alias` (PowerShell) and `scope aliases` (nushell)
+fn parse_json(output: &str) -> Result<HashMap<String, String>, Error> {
+    let aliases =
+        serde_json::from_str::<Vec<Alias>>(output.trim()).map_err(|source| Error::Parsing {
+            source,
+            diagnostics: Box::new(Diagnostics::default().with_stdout(output)),
+        })?;
+    Ok(aliases
+        .into_iter()
+        .map(|alias| (alias.name, alias.value.unwrap_or_default()))
+        .collect())
+}
+
+/// Parses the list of aliases printed by the shell
+pub(crate) fn parse_aliases(
+    kind: ShellKind,
+    output: &str,
+) -> Result<HashMap<String, String>, Error> {
+    let output = after_marker(output)?;
+    Ok(match kind {
+        ShellKind::Fish => parse_fish(output),
+        ShellKind::Csh => parse_csh(output),
+        ShellKind::PowerShell | ShellKind::Nu => parse_json(output)?,
+        _ => parse_posix(output),
+    })
+}
+
+/// Runs the shell and asks it to print aliases. See `Profile::load_aliases`.
+pub(crate) fn aliases(
+    shell: &PathBuf,
+    kind: ShellKind,
+    args: &[String],
+    options: &Options,
+) -> Result<HashMap<String, String>, Error> {
+    parse_aliases(kind, &run(shell, args, &script(kind)?, options)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bash_envvars() -> HashMap<String, String> {
+        HashMap::from([
+            ("PATH".to_owned(), "/bin".to_owned()),
+            (
+                "BASH_FUNC_greet%%".to_owned(),
+                "() {  echo \"hello\"\n}".to_owned(),
+            ),
+            ("BASH_FUNC_old()".to_owned(), "() {  echo old\n}".to_owned()),
+            // Not a function: the value doesn't start with "()"
+            ("BASH_FUNC_fake%%".to_owned(), "value".to_owned()),
+        ])
+    }
+
+    #[test]
+    fn split_functions() {
+        let (vars, functions) = split(bash_envvars());
+        assert_eq!(vars.len(), 2);
+        assert!(vars.contains_key("PATH"));
+        assert!(vars.contains_key("BASH_FUNC_fake%%"));
+        assert_eq!(functions["greet"], "{  echo \"hello\"\n}");
+        assert_eq!(functions["old"], "{  echo old\n}");
+    }
+
+    #[test]
+    fn encode_functions() {
+        let envvars = bash_envvars();
+        let (_, functions) = split(envvars.clone());
+        let encoded = encode(&functions);
+        assert_eq!(encoded["BASH_FUNC_greet%%"], envvars["BASH_FUNC_greet%%"]);
+        // Functions are always encoded in the format of bash 4.3+
+        assert_eq!(encoded["BASH_FUNC_old%%"], "() {  echo old\n}");
+    }
+
+    #[test]
+    fn posix_aliases() {
+        let bash = parse_aliases(
+            ShellKind::Bash,
+            "ENVVARS|VARS\nalias ll='ls -l'\nalias q='echo '\\''x'\\'''\n",
+        )
+        .expect("Aliases should be parsed");
+        assert_eq!(bash["ll"], "ls -l");
+        assert_eq!(bash["q"], "echo 'x'");
+        let zsh = parse_aliases(ShellKind::Zsh, "ENVVARS|VARS\nll='ls -l'\ngs=git\n")
+            .expect("Aliases should be parsed");
+        assert_eq!(zsh["ll"], "ls -l");
+        assert_eq!(zsh["gs"], "git");
+    }
+
+    #[test]
+    fn fish_aliases() {
+        let fish = parse_aliases(
+            ShellKind::Fish,
+            "ENVVARS|VARS\nalias ll 'ls -l'\nabbr -a -U -- gco 'git checkout'\n",
+        )
+        .expect("Aliases should be parsed");
+        assert_eq!(fish["ll"], "ls -l");
+        assert_eq!(fish["gco"], "git checkout");
+    }
+
+    #[test]
+    fn csh_aliases() {
+        let csh = parse_aliases(ShellKind::Csh, "ENVVARS|VARS\nll\t(ls -l)\nh\thistory\n")
+            .expect("Aliases should be parsed");
+        assert_eq!(csh["ll"], "ls -l");
+        assert_eq!(csh["h"], "history");
+    }
+
+    #[test]
+    fn json_aliases() {
+        let ps = parse_aliases(
+            ShellKind::PowerShell,
+            r#"ENVVARS|VARS [{"Name":"ls","Definition":"Get-ChildItem"},{"Name":"x","Definition":null}]"#,
+        )
+        .expect("Aliases should be parsed");
+        assert_eq!(ps["ls"], "Get-ChildItem");
+        assert_eq!(ps["x"], "");
+        let nu = parse_aliases(
+            ShellKind::Nu,
+            r#"ENVVARS|VARS [{"name":"ll","expansion":"ls -l"}]"#,
+        )
+        .expect("Aliases should be parsed");
+        assert_eq!(nu["ll"], "ls -l");
+    }
+}
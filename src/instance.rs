@@ -0,0 +1,342 @@
+use crate::{
+    cache::Cache,
+    extractor::{lock, Extractor, Options},
+    Error, Layer, PersistScope, Profile, StartupProfile, EXTRACTOR,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Instance of `envvars` with own extractor, folder of the extractor, backend, cache
+/// and options of running shells. The file of the extractor created by the instance
+/// is removed as soon as the instance is dropped.
+///
+/// Free functions (like `get_context_envvars`) and methods of `Profile` (like
+/// `Profile::load`) use a default instance, which lives until the end of the
+/// process. Each method of `Profile`, which runs the shell, has a counterpart
+/// here, which uses the extractor and options of the instance.
+///
+/// # Examples
+///
+/// ```
+/// use std::{path::PathBuf, str::FromStr};
+/// use envvars::{Envvars, Profile};
+///
+/// let envvars = Envvars::new()
+///     .with_dir(std::env::temp_dir().join("envvars-instance"))
+///     .with_env("ENVVARS_INSTANCE", "1");
+///
+/// let mut profile: Profile = if cfg!(windows) {
+///     Profile::new(&PathBuf::from_str("C:\\Program Files\\Git\\bin\\bash.exe").unwrap(), vec!["-c"], None).unwrap()
+/// } else {
+///     Profile::new(&PathBuf::from_str("/bin/bash").unwrap(), vec!["-c"], None).unwrap()
+/// };
+///
+/// envvars.load(&mut profile).unwrap();
+///
+/// assert_eq!(profile.envvars.as_ref().unwrap()["ENVVARS_INSTANCE"], "1");
+/// // The extractor is removed here
+/// drop(envvars);
+/// ```
+pub struct Envvars {
+    extractor: Arc<Mutex<Extractor>>,
+    cache: Option<Cache>,
+    options: Options,
+}
+
+impl Envvars {
+    /// Creates an instance with own extractor. The folder of the extractor is
+    /// selected automatically (see `set_extractor_dir`), the cache isn't used.
+    pub fn new() -> Self {
+        Envvars {
+            extractor: Arc::new(Mutex::new(Extractor::new())),
+            cache: None,
+            options: Options::default(),
+        }
+    }
+
+    /// Creates an instance, which shares the extractor
+    pub(crate) fn with_extractor(extractor: Arc<Mutex<Extractor>>) -> Self {
+        Envvars {
+            extractor,
+            cache: None,
+            options: Options::default(),
+        }
+    }
+
+    /// Sets the folder, where the extractor is created, if it cannot be loaded into
+    /// memory
+    ///
+    /// * `dir` - folder of the extractor
+    pub fn with_dir(self, dir: PathBuf) -> Self {
+        lock(&self.extractor).set_dir(Some(dir));
+        self
+    }
+
+    /// Defines whether the extractor can be loaded into memory (Linux only). If
+    /// `false`, the extractor is always written into the folder of the extractor.
+    /// By default `true`.
+    ///
+    /// * `memfd` - allows to load the extractor into memory
+    pub fn with_memfd(self, memfd: bool) -> Self {
+        lock(&self.extractor).memfd = memfd;
+        self
+    }
+
+    /// Sets the cache, which is used by `load`
+    ///
+    /// * `cache` - cache of loaded environment variables
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Adds an environment variable, which is passed to each shell started by the
+    /// instance
+    ///
+    /// * `key` - name of variable
+    /// * `value` - value of variable
+    pub fn with_env(mut self, key: &str, value: &str) -> Self {
+        self.options.envs.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Sets the working folder of shells started by the instance
+    ///
+    /// * `cwd` - working folder
+    pub fn with_cwd(mut self, cwd: PathBuf) -> Self {
+        self.options.cwd = Some(cwd);
+        self
+    }
+
+    /// Same as `get_context_envvars`, but uses the extractor of the instance
+    pub fn get_context_envvars(&self) -> Result<HashMap<String, String>, Error> {
+        lock(&self.extractor)
+            .get_with(None, &[], &self.options)
+            .map(|(envvars, _)| envvars)
+    }
+
+    /// Same as `Profile::load`, but uses the extractor, the cache and options of the
+    /// instance
+    ///
+    /// * `profile` - profile of the shell
+    pub fn load(&self, profile: &mut Profile) -> Result<(), Error> {
+        if let Some(cache) = self.cache.as_ref() {
+            cache.load_by(profile, &self.extractor, &self.options)
+        } else {
+            profile.load_by(&self.extractor, &self.options)
+        }
+    }
+
+    /// Same as `Profile::load_with_context`, but uses the extractor and options of
+    /// the instance
+    ///
+    /// * `profile` - profile of the shell
+    pub fn load_with_context(&self, profile: &mut Profile) -> Result<(), Error> {
+        profile.load_with_context_by(&self.extractor, &self.options)
+    }
+
+    /// Same as `Profile::load_traced`, but uses the extractor and options of the
+    /// instance
+    ///
+    /// * `profile` - profile of the shell
+    pub fn load_traced(&self, profile: &mut Profile) -> Result<(), Error> {
+        profile.load_traced_by(&self.extractor, &self.options)
+    }
+
+    /// Same as `Profile::load_profiled`, but uses the extractor and options of the
+    /// instance
+    ///
+    /// * `profile` - profile of the shell
+    pub fn load_profiled(&self, profile: &mut Profile) -> Result<StartupProfile, Error> {
+        profile.load_profiled_by(&self.extractor, &self.options)
+    }
+
+    /// Same as `Profile::load_layers`, but uses the extractor and options of the
+    /// instance
+    ///
+    /// * `profile` - profile of the shell
+    /// * `cwd` - folder to detect changes done by folder-specific hooks
+    pub fn load_layers(&self, profile: &Profile, cwd: Option<&Path>) -> Result<Vec<Layer>, Error> {
+        profile.load_layers_by(cwd, &self.extractor, &self.options)
+    }
+
+    /// Same as `Profile::load_shellvars`, but uses options of the instance
+    ///
+    /// * `profile` - profile of the shell
+    pub fn load_shellvars(&self, profile: &mut Profile) -> Result<(), Error> {
+        profile.load_shellvars_by(&self.options)
+    }
+
+    /// Same as `Profile::load_aliases`, but uses options of the instance
+    ///
+    /// * `profile` - profile of the shell
+    pub fn load_aliases(&self, profile: &mut Profile) -> Result<(), Error> {
+        profile.load_aliases_by(&self.options)
+    }
+
+    /// Same as `Profile::persist_var`, but verifies the result with the extractor
+    /// and options of the instance
+    ///
+    /// * `profile` - profile of the shell
+    /// * `name` - name of variable
+    /// * `value` - value of variable; cannot have line breaks
+    /// * `scope` - defines which startup file should be used
+    pub fn persist_var(
+        &self,
+        profile: &Profile,
+        name: &str,
+        value: &str,
+        scope: PersistScope,
+    ) -> Result<PathBuf, Error> {
+        profile.persist_var_by(name, Some(value), scope, &self.extractor, &self.options)
+    }
+
+    /// Same as `Profile::unpersist_var`, but verifies the result with the extractor
+    /// and options of the instance
+    ///
+    /// * `profile` - profile of the shell
+    /// * `name` - name of variable
+    /// * `scope` - defines which startup file should be used
+    pub fn unpersist_var(
+        &self,
+        profile: &Profile,
+        name: &str,
+        scope: PersistScope,
+    ) -> Result<PathBuf, Error> {
+        profile.persist_var_by(name, None, scope, &self.extractor, &self.options)
+    }
+
+    /// Removes the file of the extractor created by the instance, if no process uses
+    /// it. The file is created again, if the instance needs it.
+    pub fn cleanup(&self) -> Result<(), Error> {
+        lock(&self.extractor).remove().map_err(Error::Io)
+    }
+}
+
+impl Default for Envvars {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Envvars {
+    fn drop(&mut self) {
+        // The default extractor is shared with methods of `Profile`. Other clones of
+        // the extractor (like a background refresh of the cache) remove the file
+        // created by them, as soon as they are dropped (see `Extractor`).
+        if Arc::ptr_eq(&self.extractor, &EXTRACTOR) {
+            return;
+        }
+        if let Err(err) = self.cleanup() {
+            log::warn!("Fail to remove extractor: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+    use crate::{testing::TempDir, LayerKind};
+
+    fn bash() -> Profile {
+        Profile::new(&PathBuf::from("/bin/bash"), vec!["-c"], None).expect("Profile of bash")
+    }
+
+    /// Instance with the file-based extractor in own folder
+    fn instance(name: &str) -> (Envvars, TempDir) {
+        let temp = TempDir::new(&format!("instance-{name}"));
+        let envvars = Envvars::new()
+            .with_dir(temp.path().join("extractor"))
+            .with_memfd(false)
+            .with_env("ENVVARS_INSTANCE", name);
+        (envvars, temp)
+    }
+
+    fn location(envvars: &Envvars) -> PathBuf {
+        lock(&envvars.extractor)
+            .location
+            .clone()
+            .expect("Extractor should be written into file")
+    }
+
+    #[test]
+    fn options() {
+        let (envvars, temp) = instance("options");
+        let expected = Some(&String::from("options"));
+        let mut profile = bash();
+        envvars
+            .load_traced(&mut profile)
+            .expect("Envvars should be traced");
+        assert_eq!(
+            profile
+                .envvars
+                .as_ref()
+                .and_then(|v| v.get("ENVVARS_INSTANCE")),
+            expected
+        );
+        assert!(location(&envvars).starts_with(temp.path()));
+        profile.envvars = None;
+        envvars
+            .load_profiled(&mut profile)
+            .expect("Startup should be profiled");
+        assert_eq!(
+            profile
+                .envvars
+                .as_ref()
+                .and_then(|v| v.get("ENVVARS_INSTANCE")),
+            expected
+        );
+        let layers = envvars
+            .load_layers(&profile, None)
+            .expect("Layers should be loaded");
+        // The system layer is built from system files without the shell
+        assert!(layers
+            .iter()
+            .filter(|layer| layer.kind != LayerKind::System)
+            .all(|layer| layer.envvars.get("ENVVARS_INSTANCE") == expected));
+        envvars
+            .load_shellvars(&mut profile)
+            .expect("Shell variables should be loaded");
+        assert!(profile
+            .shellvars
+            .as_ref()
+            .and_then(|vars| vars.get("ENVVARS_INSTANCE"))
+            .is_some_and(|var| var.exported));
+        drop(envvars);
+    }
+
+    #[test]
+    fn drop_removes_extractor() {
+        let (envvars, _temp) = instance("drop");
+        envvars
+            .get_context_envvars()
+            .expect("Envvars should be extracted");
+        let location = location(&envvars);
+        assert!(location.exists());
+        drop(envvars);
+        assert!(!location.exists());
+    }
+
+    #[test]
+    fn drop_with_shared_extractor() {
+        let (envvars, _temp) = instance("shared");
+        envvars
+            .get_context_envvars()
+            .expect("Envvars should be extracted");
+        let location = location(&envvars);
+        // Like a background refresh of the cache, which holds the extractor
+        let shared = envvars.extractor.clone();
+        drop(envvars);
+        assert!(!location.exists());
+        lock(&shared)
+            .get(None, &[])
+            .expect("Envvars should be extracted");
+        assert!(location.exists());
+        drop(shared);
+        assert!(!location.exists());
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{
+    diff::EnvDiff,
+    extractor::{lock, Extractor, Options},
+    persist::quote,
+    system::get_system_envvars,
+    Error, ShellKind,
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Layer of the user's environment. Each layer is produced by running the shell in
+/// a specific mode.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LayerKind {
+    /// Variables defined by system files without any shell: `/etc/environment`,
+    /// pam_env and systemd `environment.d` (see `get_system_envvars`). The shell
+    /// isn't started.
+    System,
+    /// Shell is started without any startup files (`--noprofile --norc` for bash,
+    /// `-f` for zsh, `--no-config` for fish). Shows environment inherited from
+    /// the parent process.
+    Clean,
+    /// Login shell (reads `/etc/profile`, `~/.profile` etc.)
+    Login,
+    /// Interactive shell (reads `~/.bashrc`, `~/.zshrc` etc.)
+    Interactive,
+    /// Login and interactive shell, like a shell started in terminal
+    LoginInteractive,
+    /// Login and interactive shell, which changes the folder (`cd`) before reporting
+    /// variables. Shows changes done by hooks, which depend on folder: `chpwd` hooks
+    /// of zsh, `PWD` handlers of fish and `direnv` (its export is called explicitly,
+    /// because prompt hooks aren't triggered by a non-interactive script)
+    Directory,
+}
+
+impl LayerKind {
+    /// Returns a layer, which is used as a base to calculate a difference. System
+    /// and clean layers don't have a base: the clean shell doesn't read system
+    /// files, it inherits the environment of the current process, so all its
+    /// variables are the starting point.
+    pub fn base(&self) -> Option<LayerKind> {
+        match self {
+            LayerKind::System | LayerKind::Clean => None,
+            LayerKind::Login | LayerKind::Interactive => Some(LayerKind::Clean),
+            LayerKind::LoginInteractive => Some(LayerKind::Login),
+            LayerKind::Directory => Some(LayerKind::LoginInteractive),
+        }
+    }
+}
+
+/// Environment variables of a single layer
+#[derive(Serialize, Debug, Clone)]
+pub struct Layer {
+    /// Kind of layer
+    pub kind: LayerKind,
+    /// Arguments, which were used to run the shell. Empty for `LayerKind::System`
+    pub args: Vec<String>,
+    /// Working folder of the shell, if it was defined
+    pub cwd: Option<PathBuf>,
+    /// All environment variables of the layer
+    pub envvars: HashMap<String, String>,
+    /// Difference with the base layer (see `LayerKind::base`). For `LayerKind::System`
+    /// and `LayerKind::Clean` all variables are added.
+    pub diff: EnvDiff,
+}
+
+/// Returns arguments to run the shell of given kind in given layer
+pub(crate) fn args(kind: ShellKind, layer: LayerKind) -> Result<Vec<&'static str>, Error> {
+    Ok(match (kind, layer) {
+        (_, LayerKind::System) => {
+            return Err(Error::Other(String::from(
+                "Layer of system files isn't produced by the shell",
+            )))
+        }
+        (ShellKind::Bash, LayerKind::Clean) => vec!["--noprofile", "--norc", "-c"],
+        (ShellKind::Zsh, LayerKind::Clean) => vec!["-f", "-c"],
+        (ShellKind::Fish, LayerKind::Clean) => vec!["--no-config", "-c"],
+        (ShellKind::Sh | ShellKind::Ksh, LayerKind::Clean) => vec!["-c"],
+        (
+            ShellKind::Bash | ShellKind::Zsh | ShellKind::Fish | ShellKind::Sh | ShellKind::Ksh,
+            layer,
+        ) => match layer {
+            LayerKind::Login => vec!["-l", "-c"],
+            LayerKind::Interactive => vec!["-i", "-c"],
+            _ => vec!["-l", "-i", "-c"],
+        },
+        _ => return Err(Error::NotSupportedShell(format!("{kind:?}"))),
+    })
+}
+
+/// Returns the layer of variables defined by system files
+fn system() -> Result<Layer, Error> {
+    let envvars = get_system_envvars()?;
+    Ok(Layer {
+        kind: LayerKind::System,
+        args: vec![],
+        cwd: None,
+        diff: EnvDiff::between(&HashMap::new(), &envvars),
+        envvars,
+    })
+}
+
+/// Returns commands, which change the folder and trigger folder-specific hooks
+fn prelude(kind: ShellKind, cwd: &Path) -> Result<String, Error> {
+    let cwd = cwd
+        .to_str()
+        .ok_or_else(|| Error::Other(format!("Folder isn't a valid UTF-8 path: {cwd:?}")))?;
+    let direnv = |format: &str| {
+        format!("command -v direnv >/dev/null 2>&1 && eval \"$(direnv export {format})\"")
+    };
+    Ok(match kind {
+        ShellKind::Bash | ShellKind::Ksh => {
+            format!("cd -- {} || exit 1\n{}", quote(kind, cwd), direnv("bash"))
+        }
+        ShellKind::Zsh => format!("cd -- {} || exit 1\n{}", quote(kind, cwd), direnv("zsh")),
+        ShellKind::Fish => format!(
+            "cd {}; or exit 1\ntype -q direnv; and direnv export fish | source",
+            quote(kind, cwd)
+        ),
+        // direnv doesn't have an export format for POSIX sh
+        ShellKind::Sh => format!("cd -- {} || exit 1", quote(kind, cwd)),
+        _ => return Err(Error::NotSupportedShell(format!("{kind:?}"))),
+    })
+}
+
+/// Runs the shell in each mode and calculates differences between layers
+pub(crate) fn load(
+    shell: &PathBuf,
+    kind: ShellKind,
+    cwd: Option<&Path>,
+    extractor: &Mutex<Extractor>,
+    options: &Options,
+) -> Result<Vec<Layer>, Error> {
+    let mut kinds = vec![
+        LayerKind::Clean,
+        LayerKind::Login,
+        LayerKind::Interactive,
+        LayerKind::LoginInteractive,
+    ];
+    if cwd.is_some() {
+        kinds.push(LayerKind::Directory);
+    }
+    let empty = HashMap::new();
+    let mut layers: Vec<Layer> = vec![system()?];
+    for layer in kinds.into_iter() {
+        let args = args(kind, layer)?
+            .into_iter()
+            .map(|a| a.to_owned())
+            .collect::<Vec<String>>();
+        let cwd = if layer == LayerKind::Directory {
+            cwd.map(|p| p.to_path_buf())
+        } else {
+            None
+        };
+        // The shell is started in the current folder and changes it by itself to
+        // trigger hooks
+        let prelude = cwd.as_ref().map(|cwd| prelude(kind, cwd)).transpose()?;
+        let envvars = lock(extractor)
+            .get_with(
+                Some(shell),
+                &args,
+                &Options {
+                    prelude,
+                    ..options.clone()
+                },
+            )?
+            .0;
+        let base = layer
+            .base()
+            .and_then(|base| layers.iter().find(|l| l.kind == base))
+            .map(|l| &l.envvars)
+            .unwrap_or(&empty);
+        let diff = EnvDiff::between(base, &envvars);
+        layers.push(Layer {
+            kind: layer,
+            args,
+            cwd,
+            envvars,
+            diff,
+        });
+    }
+    Ok(layers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn bases() {
+        // Each layer is based on a previous one down to the clean shell
+        let mut chain = vec![LayerKind::Directory];
+        while let Some(base) = chain.last().and_then(|kind| kind.base()) {
+            chain.push(base);
+        }
+        assert_eq!(
+            chain,
+            vec![
+                LayerKind::Directory,
+                LayerKind::LoginInteractive,
+                LayerKind::Login,
+                LayerKind::Clean
+            ]
+        );
+        assert_eq!(LayerKind::Interactive.base(), Some(LayerKind::Clean));
+        assert_eq!(LayerKind::System.base(), None);
+    }
+
+    #[test]
+    fn arguments() {
+        assert_eq!(
+            args(ShellKind::Bash, LayerKind::Clean).expect("Arguments of bash"),
+            vec!["--noprofile", "--norc", "-c"]
+        );
+        assert_eq!(
+            args(ShellKind::Zsh, LayerKind::LoginInteractive).expect("Arguments of zsh"),
+            vec!["-l", "-i", "-c"]
+        );
+        assert!(matches!(
+            args(ShellKind::Bash, LayerKind::System),
+            Err(Error::Other(_))
+        ));
+        assert!(matches!(
+            args(ShellKind::Nu, LayerKind::Login),
+            Err(Error::NotSupportedShell(_))
+        ));
+    }
+
+    #[test]
+    fn folder_prelude() {
+        let cwd = Path::new("/tmp/it's here");
+        let bash = prelude(ShellKind::Bash, cwd).expect("Prelude of bash");
+        assert!(bash.starts_with(&format!(
+            "cd -- {} || exit 1",
+            quote(ShellKind::Bash, cwd.to_str().unwrap_or_default())
+        )));
+        assert!(bash.contains("direnv export bash"));
+        let fish = prelude(ShellKind::Fish, cwd).expect("Prelude of fish");
+        assert!(fish.contains("; or exit 1"));
+        assert!(!prelude(ShellKind::Sh, cwd)
+            .expect("Prelude of sh")
+            .contains("direnv"));
+        assert!(prelude(ShellKind::Nu, cwd).is_err());
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn load_with_directory() {
+        // Quotes and spaces in the name check quoting of the folder
+        let temp = TempDir::new("layers 'quoted'");
+        let dir = temp.path().to_path_buf();
+        let layers = load(
+            &PathBuf::from("/bin/bash"),
+            ShellKind::Bash,
+            Some(&dir),
+            &crate::EXTRACTOR,
+            &Options::default(),
+        )
+        .expect("Layers should be loaded");
+        // The base layer is built from system files
+        assert_eq!(layers[0].kind, LayerKind::System);
+        assert_eq!(
+            layers[0].envvars,
+            get_system_envvars().expect("System envvars should be read")
+        );
+        assert_eq!(layers[1].kind, LayerKind::Clean);
+        // Variables of the clean shell are inherited, they aren't compared with
+        // system files
+        assert_eq!(layers[1].diff.added.len(), layers[1].envvars.len());
+        let directory = layers
+            .iter()
+            .find(|l| l.kind == LayerKind::Directory)
+            .expect("Directory layer should be loaded");
+        // The folder is changed by the shell, which allows to trigger hooks
+        assert_eq!(
+            directory.envvars.get("PWD"),
+            dir.to_str().map(String::from).as_ref()
+        );
+        assert!(directory.cwd.is_some());
+    }
+}
@@ -4,7 +4,7 @@
 //! variables. If works in two steps:
 //!
 //! - detecting a list of available shells and creating `Profile` for each found
-//! shell
+//!   shell
 //! - loading a list of environment variables for selected or each shell
 //!
 //! Under the hood, `envvars` takes each shell, and executes it with a command,
@@ -17,15 +17,88 @@
 //! during detecting the shell's profiles. That's the developer's decision when it
 //! should be done for the selected or each profile.
 //!
-//! `envvars` creates a small executable application in the system's temporary folder.
+//! `envvars` creates a small executable application in a private folder of the user
+//! (`$XDG_RUNTIME_DIR/envvars`, `~/.cache/envvars` etc., see `set_extractor_dir`).
 //! This application is used to "drop" list of environment variables into `stdout`
-//! of the parent process and does nothing else. As soon as `envvars` instance is
-//! dropped, the application would be removed from the disk.
+//! of the parent process and does nothing else. As soon as an `Envvars` instance is
+//! dropped, its application would be removed from the disk. Free functions and
+//! methods of `Profile` use a default instance, which lives until the end of the
+//! process (see `cleanup`). On Linux the extractor
+//! is loaded into a sealed file in memory (`memfd_create`) and never touches the
+//! disk; the temporary folder is used only if it isn't possible.
 //!
 //! For security reasons `envvars` checks the checksum of the extractor each time
 //! before using it. If a checksum is invalid (the file was damaged/changed etc),
 //! `envars` will remove a corrupted file and create a new one.
+//!
+//! The extractor answers with a versioned envelope, which includes the version of
+//! protocol, the extractor's checksum, pid, timestamp and a nonce sent by `envvars`.
+//! Because of it, any output of startup files into `stdout` doesn't break parsing.
 //!  
+//! ## Provenance
+//! `Profile::load_traced` runs the shell with tracing and detects which startup
+//! file and line set each variable and each entry of lists of paths like `PATH`.
+//!
+//! ## Profiling of startup
+//! `Profile::load_profiled` measures time spent in each startup file of the shell
+//! and detects the slowest commands.
+//!
+//! ## Layers
+//! `Profile::load_layers` starts with variables of system files (see
+//! `get_system_envvars`) and runs the shell in successive modes (without startup
+//! files, login, interactive, login and interactive, after `cd` into a specific
+//! folder). It shows which variables each layer adds, changes or removes. Folder-specific hooks
+//! (zsh `chpwd`, fish `PWD` handlers, `direnv`) are triggered by the last layer.
+//!
+//! ## Caching
+//! `Profile::load_cached` keeps loaded environment variables in memory. Own
+//! instance of `Cache` created with `Cache::persistent` keeps them on disk too;
+//! entries on disk are invalidated as soon as startup files of the shell (like
+//! `~/.bashrc`) or files sourced by them are changed.
+//!
+//! ## Context of process
+//! `Profile::load_with_context` reports, in addition to environment variables, the
+//! working folder, umask, limits of resources and effective locale of a process
+//! started by the shell (startup files can change all of them).
+//!
+//! ## Shell variables
+//! `Profile::load_shellvars` asks the shell to print own variables, including
+//! variables, which aren't exported (like `HISTFILE`, zsh arrays or fish universal
+//! variables), with the scope and the export flag of each.
+//!
+//! ## List-typed variables
+//! fish, zsh and nushell have list-typed variables (like fish's `$PATH` or zsh's
+//! `path`). `Profile::typed_envvars` keeps such variables as `EnvValue::List`;
+//! `EnvValue::join` and `EnvValue::split` convert lists to a string with the platform
+//! separator and back.
+//!
+//! ## Functions and aliases
+//! Exported functions of bash (`BASH_FUNC_name%%` variables) are moved from
+//! `Profile::envvars` into `Profile::functions` with decoded bodies.
+//! `Profile::load_aliases` asks the shell for its aliases (and abbreviations of fish).
+//!
+//! ## Persisting variables
+//! `Profile::persist_var` writes a variable into the suitable startup file of the
+//! shell (`~/.profile`, `~/.zshrc`, `config.fish` etc) inside of a block managed by
+//! `envvars`; `Profile::unpersist_var` removes it. Both verify the result by reloading
+//! the shell and restore the file, if the result isn't expected.
+//!
+//! ## Static analysis
+//! `Profile::load_static` reads startup files of the shell without executing them and
+//! detects exported variables. Values, which depend on commands or conditions, are
+//! returned as `StaticValue::Unresolved`.
+//!
+//! ## System environment
+//! `get_system_envvars` parses files, which define environment without any shell:
+//! `/etc/environment`, `/etc/security/pam_env.conf`, `~/.pam_environment` and
+//! systemd `environment.d/*.conf`. Results can be compared with `Profile::envvars`
+//! using `EnvDiff`.
+//!
+//! ## Running processes
+//! On Linux `from_pid` reads environment variables of a running process (for example,
+//! the user's terminal or editor) from `/proc/<pid>/environ`. `login_shell` and
+//! `session_leader` help to find a suitable process.
+//!
 //! ## Unix specific
 //! `envvars` reads `/etc/shells` and analyze each shell from a list
 //!
@@ -47,6 +120,23 @@
 //! still can use `get_context_envvars()` to get a list of environment variables without
 //! the shell's context.
 //!
+//! ## Running commands
+//! `CommandExt` extends `std::process::Command` with `envs_from_profile`, which
+//! applies environment variables of a profile to the command considering the
+//! selected `MergeStrategy`.
+//!
+//! ## Applying to the current process
+//! `apply_to_current_process` moves environment variables of a profile into the
+//! running process (for example, to get the user's `PATH` in a GUI application
+//! started from a desktop launcher). Because of thread-safety caveats of
+//! `std::env::set_var` it should be called once at startup.
+//!
+//! ## Errors
+//! `Error::kind` classifies an error (shell isn't found, killed by a signal, exited
+//! with non-zero code, invalid output etc). Errors of running the shell carry
+//! `Diagnostics`: path to the shell, arguments, exit code and signal, the beginning
+//! of stdout and stderr and time of running.
+//!
 //! ## Diffrence from `std::env::vars`
 //! `envvars` actually executes each found `shell` it means: all settings of the target
 //! shell will be inited before a list of environment variables will be requested. That's
@@ -56,21 +146,89 @@
 
 #[macro_use]
 extern crate lazy_static;
-use std::{collections::HashMap, sync::Mutex};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+mod analyzer;
+mod apply;
 mod assets;
+mod cache;
 mod checksum;
+mod command;
+mod context;
+mod diff;
 mod error;
 mod extractor;
+mod functions;
+mod instance;
+mod layers;
+mod merge;
+mod persist;
+mod process;
 mod profiles;
+mod profiling;
+mod protocol;
+mod provenance;
+mod shellvars;
+mod syntax;
+mod system;
+#[cfg(test)]
+mod testing;
+mod trace;
+mod value;
 
-pub use error::Error;
-pub use extractor::cleanup;
-use extractor::Extractor;
-pub use profiles::{get as get_profiles, Profile};
+pub use analyzer::{StaticValue, StaticVar};
+pub use apply::{apply_to_current_process, ApplyReport, ApplyStrategy};
+pub use cache::Cache;
+pub use command::CommandExt;
+pub use context::{Rlimit, ShellContext};
+pub use diff::EnvDiff;
+pub use error::{Diagnostics, Error, ErrorKind};
+pub use extractor::{cleanup, cleanup_all, EXTRACTOR_DIR_VAR};
+use extractor::{lock, Extractor};
+pub use instance::Envvars;
+pub use layers::{Layer, LayerKind};
+pub use merge::MergeStrategy;
+pub use persist::PersistScope;
+pub use process::{ancestors, from_pid, from_pid_os, login_shell, parent_pid, session_leader};
+pub use profiles::{get as get_profiles, Mode, Profile, ShellKind, StartupFile};
+pub use profiling::{CommandTiming, FileTiming, StartupProfile};
+pub use provenance::{Origin, Provenance};
+pub use shellvars::{ShellVar, VarScope};
+pub use system::{get_system_envvars, get_system_files, parse_system_file, SystemFormat};
+pub use value::{EnvValue, LIST_SEPARATOR};
 
 lazy_static! {
     #[doc(hidden)]
-    static ref EXTRACTOR: Mutex<Extractor> = Mutex::new(Extractor::new());
+    static ref EXTRACTOR: Arc<Mutex<Extractor>> = Arc::new(Mutex::new(Extractor::new()));
+    /// Default instance, which is used by free functions. It shares the extractor
+    /// with methods of `Profile` and lives until the end of the process.
+    #[doc(hidden)]
+    static ref DEFAULT: Envvars = Envvars::with_extractor(EXTRACTOR.clone());
+}
+
+/// Sets the folder, where `envvars` creates the extractor, if it cannot be loaded
+/// into memory. If `None`, the folder is selected automatically: the folder
+/// defined with `ENVVARS_EXTRACTOR_DIR`, `$XDG_RUNTIME_DIR/envvars` (unix), the
+/// cache folder (`~/.cache/envvars` or `%LOCALAPPDATA%\envvars`) or a private
+/// folder of the user in the temporary folder. Folders, which belong to another
+/// user or are mounted with `noexec`, are skipped.
+///
+/// * `dir` - folder of the extractor
+///
+/// # Examples
+///
+/// ```
+/// use envvars::{get_context_envvars, set_extractor_dir};
+///
+/// set_extractor_dir(Some(std::env::temp_dir().join("envvars-example")));
+/// assert!(!get_context_envvars().unwrap().is_empty());
+/// set_extractor_dir(None);
+/// ```
+pub fn set_extractor_dir(dir: Option<PathBuf>) {
+    lock(&EXTRACTOR).set_dir(dir);
 }
 
 /// Extract environment variables without shell context.
@@ -86,8 +244,5 @@ lazy_static! {
 /// assert!(vars.contains_key("PATH") || vars.contains_key("Path") || vars.contains_key("path"));
 /// ```
 pub fn get_context_envvars() -> Result<HashMap<String, String>, Error> {
-    EXTRACTOR
-        .lock()
-        .map_err(|e| Error::PoisonError(e.to_string()))?
-        .get(None, &Vec::new())
+    DEFAULT.get_context_envvars()
 }
@@ -0,0 +1,157 @@
+use std::{collections::HashMap, env, path::PathBuf};
+
+/// Variables, which are considered as lists of paths. Such variables are merged
+/// entry by entry with `MergeStrategy::OverlayPaths`.
+const PATH_LISTS: &[&str] = &[
+    "PATH",
+    "MANPATH",
+    "INFOPATH",
+    "LD_LIBRARY_PATH",
+    "DYLD_LIBRARY_PATH",
+    "PKG_CONFIG_PATH",
+    "XDG_DATA_DIRS",
+    "XDG_CONFIG_DIRS",
+    "PYTHONPATH",
+    "PSModulePath",
+];
+
+/// Defines how environment variables of a profile are combined with the
+/// current environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergeStrategy {
+    /// Current environment is dropped, only variables of the profile are used
+    Replace,
+    /// Variables of the profile are put on top of current environment. Variables,
+    /// which aren't defined in the profile, stay untouched.
+    #[default]
+    Overlay,
+    /// Same as `Overlay`, but lists of paths (like `PATH`) are merged: entries of
+    /// the profile go first, entries of current environment, which are missed in
+    /// the profile, are appended.
+    OverlayPaths,
+}
+
+/// Returns environment variables of the current process. Unlike `std::env::vars`
+/// doesn't panic on non-unicode variables, but ignores it.
+pub(crate) fn current() -> HashMap<String, String> {
+    env::vars_os()
+        .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+        .collect()
+}
+
+fn same_key(a: &str, b: &str) -> bool {
+    if cfg!(windows) {
+        a.eq_ignore_ascii_case(b)
+    } else {
+        a == b
+    }
+}
+
+/// Returns true if variable is a list of paths
+pub(crate) fn is_path_list(key: &str) -> bool {
+    PATH_LISTS.iter().any(|k| same_key(k, key))
+}
+
+/// Looks for a variable considering case-insensitive keys on windows
+pub(crate) fn lookup<'a>(vars: &'a HashMap<String, String>, key: &str) -> Option<&'a String> {
+    vars.get(key).or_else(|| {
+        if cfg!(windows) {
+            vars.iter()
+                .find_map(|(k, v)| if same_key(k, key) { Some(v) } else { None })
+        } else {
+            None
+        }
+    })
+}
+
+/// Merges two lists of paths. Entries of `primary` go first; entries of `secondary`,
+/// which aren't present in `primary`, are appended. Empty entries are ignored.
+pub(crate) fn merge_paths(primary: &str, secondary: &str) -> String {
+    let mut entries: Vec<PathBuf> = env::split_paths(primary)
+        .filter(|p| !p.as_os_str().is_empty())
+        .collect();
+    for entry in env::split_paths(secondary) {
+        if !entry.as_os_str().is_empty() && !entries.contains(&entry) {
+            entries.push(entry);
+        }
+    }
+    env::join_paths(entries)
+        .map(|joined| joined.to_string_lossy().to_string())
+        .unwrap_or_else(|_| primary.to_owned())
+}
+
+/// Produces the full resulting environment from `base` (current environment) and
+/// variables of a profile.
+pub(crate) fn merge(
+    base: &HashMap<String, String>,
+    profile: &HashMap<String, String>,
+    strategy: MergeStrategy,
+) -> HashMap<String, String> {
+    let mut result: HashMap<String, String> = match strategy {
+        MergeStrategy::Replace => HashMap::new(),
+        MergeStrategy::Overlay | MergeStrategy::OverlayPaths => base
+            .iter()
+            .filter(|(k, _)| lookup(profile, k).is_none())
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect(),
+    };
+    for (key, value) in profile.iter() {
+        let value = match (strategy, lookup(base, key)) {
+            (MergeStrategy::OverlayPaths, Some(current)) if is_path_list(key) => {
+                merge_paths(value, current)
+            }
+            _ => value.clone(),
+        };
+        result.insert(key.clone(), value);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(list: &[(&str, String)]) -> HashMap<String, String> {
+        list.iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    fn paths(list: &[&str]) -> String {
+        env::join_paths(list)
+            .expect("Paths should be joined")
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn test() {
+        let base = vars(&[
+            ("PATH", paths(&["/usr/bin", "/opt/app/bin"])),
+            ("ONLY_BASE", String::from("base")),
+            ("SHARED", String::from("base")),
+        ]);
+        let profile = vars(&[
+            ("PATH", paths(&["/home/user/.cargo/bin", "/usr/bin"])),
+            ("ONLY_PROFILE", String::from("profile")),
+            ("SHARED", String::from("profile")),
+        ]);
+        let replaced = merge(&base, &profile, MergeStrategy::Replace);
+        assert_eq!(replaced, profile);
+        let overlaid = merge(&base, &profile, MergeStrategy::Overlay);
+        assert_eq!(overlaid.len(), 4);
+        assert_eq!(overlaid.get("ONLY_BASE"), Some(&String::from("base")));
+        assert_eq!(overlaid.get("SHARED"), Some(&String::from("profile")));
+        assert_eq!(overlaid.get("PATH"), profile.get("PATH"));
+        let merged = merge(&base, &profile, MergeStrategy::OverlayPaths);
+        assert_eq!(
+            merged.get("PATH"),
+            Some(&paths(&[
+                "/home/user/.cargo/bin",
+                "/usr/bin",
+                "/opt/app/bin"
+            ]))
+        );
+        assert_eq!(merged.get("SHARED"), Some(&String::from("profile")));
+    }
+}
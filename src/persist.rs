@@ -0,0 +1,430 @@
+use crate::{
+    error::Diagnostics,
+    extractor::{lock, Extractor, Options},
+    layers::{self, LayerKind},
+    profiles::startup,
+    syntax::is_name,
+    Error, Mode, Profile, ShellKind,
+};
+use serde::Serialize;
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Instant,
+};
+
+/// First line of the block managed by `envvars`
+const BLOCK_START: &str = "# >>> envvars >>>";
+/// Last line of the block managed by `envvars`
+const BLOCK_END: &str = "# <<< envvars <<<";
+/// Comment inside of the managed block
+const BLOCK_NOTE: &str = "# Managed by envvars. Changes inside of this block could be lost.";
+
+/// Defines to which startup file a variable is written
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PersistScope {
+    /// Startup file of login shell (`~/.profile`, `~/.bash_profile`, `~/.zprofile`,
+    /// `~/.login` etc). Variables are available in the whole session of the user.
+    Login,
+    /// Startup file of interactive shell (`~/.bashrc`, `~/.zshrc`, `~/.cshrc` etc).
+    /// Variables are available in terminals only.
+    Interactive,
+}
+
+impl PersistScope {
+    fn mode(&self) -> Mode {
+        Mode {
+            login: *self == PersistScope::Login,
+            interactive: *self == PersistScope::Interactive,
+        }
+    }
+
+    fn layer(&self) -> LayerKind {
+        match self {
+            PersistScope::Login => LayerKind::Login,
+            PersistScope::Interactive => LayerKind::Interactive,
+        }
+    }
+}
+
+/// Returns the startup file of the user to write variables of the given scope.
+/// Existing files, which are read by the shell, are preferred.
+fn target(kind: ShellKind, scope: PersistScope) -> Result<PathBuf, Error> {
+    let names: &[&str] = match (kind, scope) {
+        // ~/.profile goes last: creating ~/.bash_profile would hide existing ~/.profile
+        (ShellKind::Bash, PersistScope::Login) => &[".bash_profile", ".bash_login", ".profile"],
+        (ShellKind::Bash, PersistScope::Interactive) => &[".bashrc"],
+        (ShellKind::Zsh, PersistScope::Login) => &[".zprofile"],
+        (ShellKind::Zsh, PersistScope::Interactive) => &[".zshrc"],
+        (ShellKind::Sh | ShellKind::Ksh, PersistScope::Login) => &[".profile"],
+        // File defined by $ENV or ~/.kshrc
+        (ShellKind::Sh | ShellKind::Ksh, PersistScope::Interactive) => &[],
+        (ShellKind::Csh, PersistScope::Login) => &[".login"],
+        (ShellKind::Csh, PersistScope::Interactive) => &[".tcshrc", ".cshrc"],
+        (ShellKind::Fish, _) => &["config.fish"],
+        (ShellKind::Nu, _) => &["env.nu"],
+        (ShellKind::PowerShell, _) => &["Microsoft.PowerShell_profile.ps1"],
+        (ShellKind::Cmd | ShellKind::Unknown, _) => &[],
+    };
+    let candidates = startup::files(kind, scope.mode())
+        .into_iter()
+        .filter(|f| !f.path.starts_with("/etc"))
+        .filter(|f| {
+            names.is_empty()
+                || f.path
+                    .file_name()
+                    .map(|n| names.iter().any(|name| n == *name))
+                    .unwrap_or(false)
+        })
+        .collect::<Vec<startup::StartupFile>>();
+    candidates
+        .iter()
+        .find(|f| f.read)
+        .or(candidates.last())
+        .map(|f| f.path.clone())
+        .ok_or(Error::NotSupportedShell(format!("{kind:?}")))
+}
+
+/// Returns the beginning of a line, which sets the variable
+fn prefix(kind: ShellKind, name: &str) -> Result<String, Error> {
+    Ok(match kind {
+        ShellKind::Bash | ShellKind::Zsh | ShellKind::Sh | ShellKind::Ksh => {
+            format!("export {name}=")
+        }
+        ShellKind::Fish => format!("set -gx {name} "),
+        ShellKind::Csh => format!("setenv {name} "),
+        ShellKind::PowerShell => format!("$env:{name} = "),
+        ShellKind::Nu => format!("$env.{name} = "),
+        ShellKind::Cmd | ShellKind::Unknown => {
+            return Err(Error::NotSupportedShell(format!("{kind:?}")))
+        }
+    })
+}
+
+/// Returns the value quoted for the given kind of shell
+pub(crate) fn quote(kind: ShellKind, value: &str) -> String {
+    match kind {
+        ShellKind::Fish => format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'")),
+        ShellKind::Csh => format!("'{}'", value.replace('\'', "'\\''").replace('!', "\\!")),
+        ShellKind::PowerShell => format!("'{}'", value.replace('\'', "''")),
+        ShellKind::Nu => {
+            let mut hashes = String::from("#");
+            while value.contains(&format!("'{hashes}")) {
+                hashes.push('#');
+            }
+            format!("r{hashes}'{value}'{hashes}")
+        }
+        _ => format!("'{}'", value.replace('\'', "'\\''")),
+    }
+}
+
+/// Returns new content of the startup file, where the variable is set (or removed,
+/// if value is `None`) inside of the managed block. Returns `None` if the managed
+/// block isn't closed.
+fn update(content: &str, prefix: &str, line: Option<String>) -> Option<String> {
+    let lines = content.lines().collect::<Vec<&str>>();
+    let start = lines.iter().position(|l| l.trim() == BLOCK_START);
+    let end = start.and_then(|start| {
+        lines
+            .iter()
+            .skip(start)
+            .position(|l| l.trim() == BLOCK_END)
+            .map(|p| p + start)
+    });
+    if start.is_some() && end.is_none() {
+        return None;
+    }
+    let (before, mut block, after) = match (start, end) {
+        (Some(start), Some(end)) => (
+            &lines[..start],
+            lines[start + 1..end]
+                .iter()
+                .filter(|l| **l != BLOCK_NOTE)
+                .map(|l| l.to_string())
+                .collect::<Vec<String>>(),
+            &lines[end + 1..],
+        ),
+        _ => (&lines[..], vec![], &lines[lines.len()..]),
+    };
+    let position = block.iter().position(|l| l.starts_with(prefix));
+    match (position, line) {
+        (Some(position), Some(line)) => block[position] = line,
+        (Some(position), None) => {
+            block.remove(position);
+        }
+        (None, Some(line)) => block.push(line),
+        (None, None) => {}
+    }
+    let mut result = before
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<String>>();
+    if block.is_empty() && start.is_some() && after.is_empty() {
+        // Empty line before the block was added with the block
+        if result.last().map(|l| l.is_empty()).unwrap_or(false) {
+            result.pop();
+        }
+    }
+    if !block.is_empty() {
+        if start.is_none() && result.last().map(|l| !l.is_empty()).unwrap_or(false) {
+            result.push(String::new());
+        }
+        result.push(BLOCK_START.to_owned());
+        result.push(BLOCK_NOTE.to_owned());
+        result.extend(block);
+        result.push(BLOCK_END.to_owned());
+    }
+    result.extend(after.iter().map(|l| l.to_string()));
+    Some(if result.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", result.join("\n"))
+    })
+}
+
+/// Returns the file, which should be changed: dotfiles are often symlinks (for
+/// example, into a repository of dotfiles), the link itself is kept.
+fn resolve(path: &Path) -> PathBuf {
+    fs::canonicalize(path)
+        .or_else(|_| {
+            // Link to a file, which doesn't exist yet
+            fs::read_link(path).map(|target| match path.parent() {
+                Some(parent) => parent.join(target),
+                None => target,
+            })
+        })
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Writes the startup file atomically: the content is written into a temporary
+/// file next to the target, which replaces the target. Permissions of the target
+/// are kept; if the path is a symlink, the file it refers to is replaced.
+fn write(path: &Path, content: &str) -> Result<(), Error> {
+    let target = resolve(path);
+    let name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let tmp = target.with_file_name(format!(".{name}.envvars-{}.tmp", std::process::id()));
+    let written = (|| -> Result<(), io::Error> {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&tmp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        if let Ok(metadata) = fs::metadata(&target) {
+            fs::set_permissions(&tmp, metadata.permissions())?;
+        }
+        fs::rename(&tmp, &target)
+    })();
+    if let Err(err) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(Error::Io(err));
+    }
+    Ok(())
+}
+
+/// Puts back the original content of the startup file. If the file didn't exist,
+/// it's removed.
+fn restore(path: &Path, content: Option<&str>) -> Result<(), Error> {
+    match content {
+        Some(content) => write(path, content),
+        None => fs::remove_file(resolve(path)).map_err(Error::Io),
+    }
+}
+
+/// Reloads the shell and checks the variable has the expected value. If the
+/// variable is removed, it should be gone or have the value inherited from the
+/// current process.
+fn verify(
+    profile: &Profile,
+    name: &str,
+    value: Option<&str>,
+    scope: PersistScope,
+    path: &Path,
+    extractor: &Mutex<Extractor>,
+    options: &Options,
+) -> Result<(), Error> {
+    let args: Vec<String> = layers::args(profile.kind(), scope.layer())
+        .map(|args| args.into_iter().map(|a| a.to_owned()).collect())
+        .unwrap_or(profile.args().to_vec());
+    let started = Instant::now();
+    let (envvars, stderr) = lock(extractor).get_with(Some(&profile.path), &args, options)?;
+    let actual = envvars.get(name).map(|v| v.as_str());
+    let applied = match value {
+        Some(value) => actual == Some(value),
+        None => {
+            let inherited = options
+                .envs
+                .iter()
+                .rev()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.clone())
+                .or_else(|| env::var(name).ok());
+            actual.is_none() || actual == inherited.as_deref()
+        }
+    };
+    if applied {
+        return Ok(());
+    }
+    Err(Error::NotPersisted {
+        name: name.to_owned(),
+        path: path.to_owned(),
+        diagnostics: Box::new(Diagnostics::default().with_stderr(&stderr)),
+    }
+    .with_command(Some(&profile.path), &args, started.elapsed()))
+}
+
+/// Writes (or removes, if value is `None`) the variable into the startup file and
+/// verifies the result by reloading the shell with the given extractor and options.
+/// See `Profile::persist_var`.
+pub(crate) fn persist(
+    profile: &Profile,
+    name: &str,
+    value: Option<&str>,
+    scope: PersistScope,
+    extractor: &Mutex<Extractor>,
+    options: &Options,
+) -> Result<PathBuf, Error> {
+    if !is_name(name) {
+        return Err(Error::InvalidVariable(name.to_owned()));
+    }
+    if value
+        .map(|v| v.contains(['\n', '\r', '\0']))
+        .unwrap_or(false)
+    {
+        return Err(Error::InvalidVariable(name.to_owned()));
+    }
+    let kind = profile.kind();
+    let prefix = prefix(kind, name)?;
+    let line = value.map(|value| format!("{prefix}{}", quote(kind, value)));
+    let path = target(kind, scope)?;
+    let original = if path.exists() {
+        Some(fs::read_to_string(&path).map_err(Error::Io)?)
+    } else {
+        None
+    };
+    let content = original.as_deref().unwrap_or_default();
+    let updated =
+        update(content, &prefix, line).ok_or_else(|| Error::UnclosedBlock(path.clone()))?;
+    if updated == content {
+        return verify(profile, name, value, scope, &path, extractor, options).map(|_| path);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(Error::Io)?;
+    }
+    write(&path, &updated)?;
+    if let Err(err) = verify(profile, name, value, scope, &path, extractor, options) {
+        if let Err(err) = restore(&path, original.as_deref()) {
+            log::warn!("Fail to restore {path:?}: {err}");
+        }
+        return Err(err);
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn bash(value: &str) -> (String, Option<String>) {
+        let prefix = prefix(ShellKind::Bash, "A").expect("Prefix should be created");
+        let line = Some(format!("{prefix}{}", quote(ShellKind::Bash, value)));
+        (prefix, line)
+    }
+
+    const CONTENT: &str = "alias ll='ls -l'\n";
+
+    #[test]
+    fn add() {
+        let (prefix, line) = bash("it's");
+        let added = update(CONTENT, &prefix, line.clone()).expect("Content should be updated");
+        assert_eq!(
+            added,
+            format!("alias ll='ls -l'\n\n{BLOCK_START}\n{BLOCK_NOTE}\nexport A='it'\\''s'\n{BLOCK_END}\n")
+        );
+        // Repeated call doesn't change anything
+        assert_eq!(update(&added, &prefix, line), Some(added));
+    }
+
+    #[test]
+    fn change() {
+        let (prefix, line) = bash("a");
+        let added = update(CONTENT, &prefix, line).expect("Content should be updated");
+        let changed = update(&added, &prefix, bash("b").1).expect("Content should be updated");
+        assert!(changed.contains("export A='b'\n") && !changed.contains("export A='a'"));
+        assert_eq!(changed.matches(BLOCK_START).count(), 1);
+    }
+
+    #[test]
+    fn remove() {
+        let (prefix, line) = bash("a");
+        let added = update(CONTENT, &prefix, line).expect("Content should be updated");
+        // The block is removed with the last variable
+        assert_eq!(update(&added, &prefix, None).as_deref(), Some(CONTENT));
+        assert_eq!(update(CONTENT, &prefix, None).as_deref(), Some(CONTENT));
+    }
+
+    #[test]
+    fn unclosed_block() {
+        let (prefix, line) = bash("a");
+        let broken = format!("{CONTENT}{BLOCK_START}\nexport B='b'\n");
+        assert_eq!(update(&broken, &prefix, line), None);
+        assert_eq!(update(&broken, &prefix, None), None);
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote(ShellKind::Bash, "it's"), "'it'\\''s'");
+        assert_eq!(quote(ShellKind::Fish, "a'b"), "'a\\'b'");
+        assert_eq!(quote(ShellKind::Csh, "a!b"), "'a\\!b'");
+        assert_eq!(quote(ShellKind::PowerShell, "a'b"), "'a''b'");
+        assert_eq!(quote(ShellKind::Nu, "a'#b"), "r##'a'#b'##");
+    }
+
+    #[test]
+    fn restoring() {
+        let temp = TempDir::new("persist-restoring");
+        let path = temp.path().join(".profile");
+        fs::write(&path, "changed").expect("File should be written");
+        restore(&path, Some(CONTENT)).expect("File should be restored");
+        assert_eq!(fs::read_to_string(&path).ok().as_deref(), Some(CONTENT));
+        // The file didn't exist before
+        restore(&path, None).expect("File should be removed");
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let temp = TempDir::new("persist-symlinked");
+        let dotfiles = temp.path().join("dotfiles");
+        fs::create_dir(&dotfiles).expect("Folder should be created");
+        let file = dotfiles.join("profile");
+        fs::write(&file, "changed").expect("File should be written");
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640))
+            .expect("Permissions should be set");
+        let link = temp.path().join(".profile");
+        symlink(&file, &link).expect("Link should be created");
+        write(&link, CONTENT).expect("File should be written");
+        assert!(fs::symlink_metadata(&link)
+            .expect("Link should exist")
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&file).ok().as_deref(), Some(CONTENT));
+        assert_eq!(
+            fs::metadata(&file)
+                .expect("File should exist")
+                .permissions()
+                .mode()
+                & 0o777,
+            0o640
+        );
+        // Only the file and the link are there, without temporary files
+        assert_eq!(fs::read_dir(&dotfiles).map(|d| d.count()).ok(), Some(1));
+    }
+}
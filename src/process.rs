@@ -0,0 +1,298 @@
+use crate::{Error, Mode, ShellKind};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(unix)]
+use std::os::unix::ffi::OsStringExt;
+
+/// Fields of `/proc/<pid>/stat`, which are needed to walk through processes
+struct Stat {
+    ppid: u32,
+    session: u32,
+}
+
+fn proc_path(pid: u32, file: &str) -> PathBuf {
+    Path::new("/proc").join(pid.to_string()).join(file)
+}
+
+fn read_proc(pid: u32, file: &str) -> Result<Vec<u8>, Error> {
+    if !cfg!(target_os = "linux") {
+        return Err(Error::NotSupportedPlatform);
+    }
+    let path = proc_path(pid, file);
+    fs::read(&path).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => Error::ProcessNotFound(pid),
+        io::ErrorKind::PermissionDenied => Error::PermissionDenied(path),
+        _ => Error::Io(err),
+    })
+}
+
+fn stat(pid: u32) -> Result<Stat, Error> {
+    let content = String::from_utf8_lossy(&read_proc(pid, "stat")?).to_string();
+    // Name of executable is in brackets and can include spaces and brackets
+    let fields = content
+        .rsplit_once(')')
+        .map(|(_, fields)| fields.split_whitespace().collect::<Vec<&str>>())
+        .unwrap_or_default();
+    let field = |n: usize| -> Result<u32, Error> {
+        fields
+            .get(n)
+            .and_then(|f| f.parse::<u32>().ok())
+            .ok_or(Error::Other(format!("Fail to parse /proc/{pid}/stat")))
+    };
+    Ok(Stat {
+        ppid: field(1)?,
+        session: field(3)?,
+    })
+}
+
+fn split_nul(bytes: Vec<u8>) -> Vec<Vec<u8>> {
+    bytes
+        .split(|b| *b == 0)
+        .filter(|part| !part.is_empty())
+        .map(|part| part.to_vec())
+        .collect()
+}
+
+#[cfg(unix)]
+fn to_os_string(bytes: Vec<u8>) -> OsString {
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+fn to_os_string(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).to_string())
+}
+
+/// Parses content of `/proc/<pid>/environ`: pairs "name=value" separated by NUL.
+/// An entry without "=" is returned as a name with an empty value.
+fn parse_environ(content: Vec<u8>) -> Vec<(OsString, OsString)> {
+    let mut envvars: Vec<(OsString, OsString)> = vec![];
+    for mut pair in split_nul(content) {
+        // Name cannot be empty, but can start with "=" on some systems. An entry
+        // without "=" (possible, if the process is started with such environment)
+        // is kept with an empty value.
+        let Some(pos) = pair.iter().skip(1).position(|b| *b == b'=').map(|p| p + 1) else {
+            envvars.push((to_os_string(pair), OsString::new()));
+            continue;
+        };
+        let value = pair.split_off(pos + 1);
+        pair.truncate(pos);
+        envvars.push((to_os_string(pair), to_os_string(value)));
+    }
+    envvars
+}
+
+/// Reads environment variables of a running process from `/proc/<pid>/environ`
+/// without decoding of names and values. Pairs are returned in the original order;
+/// duplicated names (possible, if the process is started with such environment) are
+/// kept. An entry without "=" (also possible, it's up to the process, which starts
+/// another one) is returned as a name with an empty value, so it cannot be
+/// distinguished from an entry like "NAME=". Note, it's the environment the process was started with; changes done by the
+/// process itself later aren't visible.
+///
+/// Returns `Error::PermissionDenied` if the process belongs to another user and
+/// `Error::ProcessNotFound` if the process doesn't exist. Supported on Linux only.
+pub fn from_pid_os(pid: u32) -> Result<Vec<(OsString, OsString)>, Error> {
+    Ok(parse_environ(read_proc(pid, "environ")?))
+}
+
+/// Same as `from_pid_os`, but returns the same map as `Profile::envvars`. If a name is
+/// duplicated, the first value is kept (as `getenv` does). If any name or value isn't
+/// a valid unicode string, `Error::Decoding` is returned; use `from_pid_os` in this
+/// case.
+///
+/// # Examples
+///
+/// ```
+/// use envvars::from_pid;
+///
+/// if cfg!(target_os = "linux") {
+///     let vars = from_pid(std::process::id()).unwrap();
+///     assert!(vars.contains_key("PATH"));
+/// }
+/// ```
+pub fn from_pid(pid: u32) -> Result<HashMap<String, String>, Error> {
+    let decode = |s: OsString| {
+        String::from_utf8(s.into_encoded_bytes()).map_err(|e| Error::Decoding(e.utf8_error()))
+    };
+    let mut envvars: HashMap<String, String> = HashMap::new();
+    for (key, value) in from_pid_os(pid)? {
+        let (key, value) = (decode(key)?, decode(value)?);
+        envvars.entry(key).or_insert(value);
+    }
+    Ok(envvars)
+}
+
+/// Returns pid of parent process. Returns `None` for processes without parent (like
+/// `init`). Supported on Linux only.
+pub fn parent_pid(pid: u32) -> Result<Option<u32>, Error> {
+    let ppid = stat(pid)?.ppid;
+    Ok(if ppid == 0 { None } else { Some(ppid) })
+}
+
+/// Returns the chain of parents of the process; the direct parent goes first. If
+/// access to some process is denied or some process exits during the walk, the chain
+/// ends on it. Supported on Linux only.
+pub fn ancestors(pid: u32) -> Result<Vec<u32>, Error> {
+    let mut chain: Vec<u32> = vec![];
+    let mut current = pid;
+    loop {
+        let parent = match parent_pid(current) {
+            Ok(Some(parent)) => parent,
+            Ok(None) => break,
+            Err(Error::PermissionDenied(_) | Error::ProcessNotFound(_)) if current != pid => break,
+            Err(err) => return Err(err),
+        };
+        if chain.contains(&parent) {
+            break;
+        }
+        chain.push(parent);
+        current = parent;
+    }
+    Ok(chain)
+}
+
+/// Returns pid of the session leader of the process. Usually it's a login shell or
+/// a terminal emulator. Supported on Linux only.
+pub fn session_leader(pid: u32) -> Result<u32, Error> {
+    let session = stat(pid)?.session;
+    if session == 0 {
+        // Process belongs to the kernel's session, the topmost ancestor is returned
+        return Ok(ancestors(pid)?.last().copied().unwrap_or(pid));
+    }
+    Ok(session)
+}
+
+/// Checks the command line (split by NUL) is a command line of a login shell
+fn is_login(args: &[Vec<u8>]) -> bool {
+    let Some(name) = args.first().map(|a| String::from_utf8_lossy(a).to_string()) else {
+        return false;
+    };
+    let dashed = name.starts_with('-');
+    let name = name.strip_prefix('-').unwrap_or(&name);
+    if ShellKind::from_path(Path::new(name)) == ShellKind::Unknown {
+        return false;
+    }
+    let args = args
+        .iter()
+        .skip(1)
+        .map(|a| String::from_utf8_lossy(a).to_string())
+        .collect::<Vec<String>>();
+    dashed || Mode::from_args(&args).login
+}
+
+/// Walks up through parents of the process (the process itself included) and returns
+/// pid of the nearest login shell. A process is considered as a login shell if its
+/// name is a name of known shell (see `ShellKind::from_path`) and the name starts
+/// with "-" (like "-bash") or it's started with `-l` or `--login`. If some
+/// ancestor exits during the walk, the walk stops on it. Supported on Linux only.
+///
+/// # Examples
+///
+/// ```
+/// use envvars::{from_pid, login_shell};
+///
+/// if cfg!(target_os = "linux") {
+///     if let Some(pid) = login_shell(std::process::id()).unwrap() {
+///         let vars = from_pid(pid);
+///         println!("Login shell {pid}: {vars:?}");
+///     }
+/// }
+/// ```
+pub fn login_shell(pid: u32) -> Result<Option<u32>, Error> {
+    for candidate in std::iter::once(pid).chain(ancestors(pid)?) {
+        let args = match read_proc(candidate, "cmdline") {
+            Ok(cmdline) => split_nul(cmdline),
+            Err(Error::PermissionDenied(_)) => continue,
+            // The ancestor has exited, its parents aren't known anymore
+            Err(Error::ProcessNotFound(_)) if candidate != pid => break,
+            Err(err) => return Err(err),
+        };
+        if is_login(&args) {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+    use super::*;
+
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn test() {
+        let mut child = Command::new("sleep")
+            .arg("30")
+            .env_clear()
+            .env("A", "1")
+            .env("B", "x=y")
+            .stdin(Stdio::null())
+            .spawn()
+            .expect("Process should be started");
+        let envvars = from_pid_os(child.id());
+        let _ = child.kill();
+        let _ = child.wait();
+        assert_eq!(
+            envvars.expect("Envvars should be read"),
+            vec![
+                (OsString::from("A"), OsString::from("1")),
+                (OsString::from("B"), OsString::from("x=y"))
+            ]
+        );
+        let pid = std::process::id();
+        let parents = ancestors(pid).expect("Parents should be found");
+        assert_eq!(
+            parent_pid(pid).expect("Parent should be found"),
+            parents.first().copied()
+        );
+        assert!(session_leader(pid).is_ok());
+        assert!(matches!(
+            from_pid(u32::MAX),
+            Err(Error::ProcessNotFound(u32::MAX))
+        ));
+    }
+
+    #[test]
+    fn environ() {
+        let pairs = parse_environ(b"A=1\0=C:=C:\\\0BROKEN\0B=x=y\0\0".to_vec());
+        let pairs: Vec<(&str, &str)> = pairs
+            .iter()
+            .map(|(k, v)| {
+                (
+                    k.to_str().unwrap_or_default(),
+                    v.to_str().unwrap_or_default(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![("A", "1"), ("=C:", "C:\\"), ("BROKEN", ""), ("B", "x=y")]
+        );
+    }
+
+    #[test]
+    fn login() {
+        let args = |args: &[&str]| {
+            args.iter()
+                .map(|a| a.as_bytes().to_vec())
+                .collect::<Vec<Vec<u8>>>()
+        };
+        assert!(is_login(&args(&["-bash"])));
+        assert!(is_login(&args(&["-zsh", "-i"])));
+        assert!(is_login(&args(&["/bin/bash", "-l"])));
+        assert!(is_login(&args(&["/usr/bin/fish", "--login"])));
+        assert!(is_login(&args(&["sh", "-lc", "exec app"])));
+        assert!(!is_login(&args(&["/bin/bash", "-c", "ls -l"])));
+        assert!(!is_login(&args(&["ls", "-l"])));
+        assert!(!is_login(&args(&["-agent"])));
+        assert!(!is_login(&[]));
+    }
+}
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Family of shell. Is detected by name of shell's executable file and defines
+/// syntax and startup files of the shell.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShellKind {
+    /// bash, rbash
+    Bash,
+    /// zsh
+    Zsh,
+    /// fish
+    Fish,
+    /// POSIX sh and compatible: sh, dash, ash
+    Sh,
+    /// ksh, mksh, pdksh
+    Ksh,
+    /// csh, tcsh
+    Csh,
+    /// nushell
+    Nu,
+    /// Windows PowerShell and PowerShell Core
+    PowerShell,
+    /// Windows Command Prompt
+    Cmd,
+    /// Any other shell
+    Unknown,
+}
+
+impl ShellKind {
+    /// Detects kind of shell by name of executable file
+    pub fn from_path(path: &Path) -> Self {
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match name.as_str() {
+            "bash" | "rbash" => ShellKind::Bash,
+            "zsh" => ShellKind::Zsh,
+            "fish" => ShellKind::Fish,
+            "sh" | "dash" | "ash" => ShellKind::Sh,
+            "ksh" | "mksh" | "pdksh" | "ksh93" => ShellKind::Ksh,
+            "csh" | "tcsh" => ShellKind::Csh,
+            "nu" => ShellKind::Nu,
+            "pwsh" | "powershell" => ShellKind::PowerShell,
+            "cmd" => ShellKind::Cmd,
+            _ => ShellKind::Unknown,
+        }
+    }
+}
+
+/// Mode in which shell is started. Mode defines which startup files shell reads.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Mode {
+    /// Shell is started as login shell (`-l`, `--login`)
+    pub login: bool,
+    /// Shell is started as interactive shell (`-i`)
+    pub interactive: bool,
+}
+
+impl Mode {
+    /// Detects mode by shell's arguments
+    pub fn from_args(args: &[String]) -> Self {
+        let mut mode = Mode::default();
+        for arg in args.iter() {
+            if arg == "--login" || arg == "-login" {
+                mode.login = true;
+            } else if let Some(short) = arg.strip_prefix('-') {
+                // Only combined short flags like "-l", "-il", "-lc" are considered
+                if short.chars().all(|c| matches!(c, 'c' | 'i' | 'l')) {
+                    mode.login = mode.login || short.contains('l');
+                    mode.interactive = mode.interactive || short.contains('i');
+                }
+            }
+        }
+        mode
+    }
+}
@@ -1,14 +1,33 @@
-use crate::{Error, EXTRACTOR};
+use crate::{
+    analyzer::{self, StaticVar},
+    cache::CACHE,
+    context::ShellContext,
+    extractor::{lock, Extractor, Options},
+    functions,
+    layers::{self, Layer},
+    persist::{self, PersistScope},
+    profiling::{self, StartupProfile},
+    provenance::{self, Provenance},
+    shellvars::{self, ShellVar},
+    trace, EnvValue, Error, DEFAULT,
+};
 use serde::Serialize;
 use std::{
     collections::HashMap,
     fs,
     path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
+pub mod kind;
+pub mod startup;
 pub mod unix;
 pub mod windows;
 
+pub use kind::{Mode, ShellKind};
+pub use startup::StartupFile;
+
 /// Definition of shell profile
 #[derive(Serialize, Debug, Clone)]
 pub struct Profile {
@@ -22,6 +41,23 @@ pub struct Profile {
     /// by default `envvars = None`. To load data should be used method `load`, which will
     /// make attempt to detect environment variables.
     pub envvars: Option<HashMap<String, String>>,
+    /// Origins of environment variables. By default `provenance = None`. To load data
+    /// should be used method `load_traced`.
+    pub provenance: Option<Provenance>,
+    /// Exported functions of bash (variables like `BASH_FUNC_name%%`) with decoded
+    /// bodies. Such variables are moved from `envvars` into this map, when `envvars`
+    /// are loaded.
+    pub functions: Option<HashMap<String, String>>,
+    /// Aliases of the shell. By default `aliases = None`. To load data should be used
+    /// method `load_aliases`.
+    pub aliases: Option<HashMap<String, String>>,
+    /// Context of a process started by the shell: working folder, umask, limits of
+    /// resources, locale. By default `context = None`. To load data should be used
+    /// method `load_with_context`.
+    pub context: Option<ShellContext>,
+    /// Variables of the shell itself, including not exported. By default
+    /// `shellvars = None`. To load data should be used method `load_shellvars`.
+    pub shellvars: Option<HashMap<String, ShellVar>>,
     /// true - if path to executable file of shell is symlink to another location.
     pub symlink: bool,
     /// Private field to store arguments needed to execute shell in right way to grab list
@@ -33,10 +69,10 @@ impl Profile {
     /// Creates shell's profile description
     /// * `shell` - path to shell's executable file
     /// * `args` - list of arguments needed to pass a command into shell. For example: "-c"
-    /// to have a full command like: "/etc/bin/bash -c cmd"
+    ///   to have a full command like: "/etc/bin/bash -c cmd"
     /// * `name` - optional name for profile. For unix based systems it will be name of
-    /// executable file, like "bash", "fish" etc. For windows better to provide name to
-    /// have it like "GitBash", "PowerShell" etc.
+    ///   executable file, like "bash", "fish" etc. For windows better to provide name to
+    ///   have it like "GitBash", "PowerShell" etc.
     pub fn new(shell: &PathBuf, args: Vec<&str>, name: Option<&str>) -> Result<Self, Error> {
         let path = Path::new(shell);
         if !path.exists() {
@@ -60,6 +96,11 @@ impl Profile {
             name,
             path: shell.clone(),
             envvars: None,
+            provenance: None,
+            functions: None,
+            aliases: None,
+            context: None,
+            shellvars: None,
             symlink,
             args: args
                 .into_iter()
@@ -99,14 +140,561 @@ impl Profile {
     /// }
     /// ```
     pub fn load(&mut self) -> Result<(), Error> {
-        self.envvars = Some(
-            EXTRACTOR
-                .lock()
-                .map_err(|e| Error::PoisonError(e.to_string()))?
-                .get(Some(&self.path), &self.args)?,
-        );
+        DEFAULT.load(self)
+    }
+
+    /// Same as `load`, but uses the given extractor and options (see `Envvars`)
+    pub(crate) fn load_by(
+        &mut self,
+        extractor: &Mutex<Extractor>,
+        options: &Options,
+    ) -> Result<(), Error> {
+        let (envvars, _) = lock(extractor).get_with(Some(&self.path), &self.args, options)?;
+        self.set_envvars(envvars);
+        Ok(())
+    }
+
+    /// Same as `load`, but also captures the context of a process started by the
+    /// shell: working folder, umask, limits of resources (`ulimit`) and effective
+    /// locale. The context is saved in `self.context`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{path::PathBuf, str::FromStr};
+    /// use envvars::Profile;
+    ///
+    /// let mut profile: Profile = if cfg!(windows) {
+    ///     Profile::new(&PathBuf::from_str("C:\\Program Files\\Git\\bin\\bash.exe").unwrap(), vec!["-c"], None).unwrap()
+    /// } else {
+    ///     Profile::new(&PathBuf::from_str("/bin/bash").unwrap(), vec!["-c"], None).unwrap()
+    /// };
+    ///
+    /// profile.load_with_context().unwrap();
+    ///
+    /// let context = profile.context.as_ref().unwrap();
+    /// if cfg!(unix) {
+    ///     assert!(context.umask.is_some());
+    ///     assert!(context.rlimits.contains_key("nofile"));
+    /// }
+    /// ```
+    pub fn load_with_context(&mut self) -> Result<(), Error> {
+        DEFAULT.load_with_context(self)
+    }
+
+    /// Same as `load_with_context`, but uses the given extractor and options (see
+    /// `Envvars`)
+    pub(crate) fn load_with_context_by(
+        &mut self,
+        extractor: &Mutex<Extractor>,
+        options: &Options,
+    ) -> Result<(), Error> {
+        let (envvars, context, _) =
+            lock(extractor).get_with_context(Some(&self.path), &self.args, options)?;
+        self.set_envvars(envvars);
+        self.context = Some(context);
         Ok(())
     }
+
+    /// Same as `load`, but runs shell with tracing (`xtrace` for bash, zsh, sh and ksh;
+    /// `fish_trace` for fish) to detect which startup file and line set each variable.
+    /// Results are saved in `self.envvars` and `self.provenance`.
+    ///
+    /// Not all shells report locations of traced commands: fish doesn't report files
+    /// and lines, sh and ksh report only lines, bash ignores the trace prompt running
+    /// as root. For such shells `Origin::Startup` has `None` instead of file or line.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{path::PathBuf, str::FromStr};
+    /// use envvars::{Origin, Profile};
+    ///
+    /// let mut profile: Profile = if cfg!(windows) {
+    ///     Profile::new(&PathBuf::from_str("C:\\Program Files\\Git\\bin\\bash.exe").unwrap(), vec!["-c"], None).unwrap()
+    /// } else {
+    ///     Profile::new(&PathBuf::from_str("/bin/bash").unwrap(), vec!["-c"], None).unwrap()
+    /// };
+    ///
+    /// profile.load_traced().unwrap();
+    ///
+    /// if let Some(provenance) = profile.provenance.as_ref() {
+    ///     if let Some(Origin::Startup { file, line }) = provenance.variables.get("PATH") {
+    ///         println!("PATH is set in {file:?}:{line:?}");
+    ///     }
+    /// }
+    /// ```
+    pub fn load_traced(&mut self) -> Result<(), Error> {
+        DEFAULT.load_traced(self)
+    }
+
+    /// Same as `load_traced`, but uses the given extractor and options (see
+    /// `Envvars`)
+    pub(crate) fn load_traced_by(
+        &mut self,
+        extractor: &Mutex<Extractor>,
+        options: &Options,
+    ) -> Result<(), Error> {
+        let kind = self.kind();
+        let tracing = trace::setup(kind, false)?;
+        let args = self.args_with(tracing.args.clone());
+        let (mut envvars, stderr) = lock(extractor).get_with(
+            Some(&self.path),
+            &args,
+            &Options {
+                envs: [options.envs.clone(), tracing.envs.clone()].concat(),
+                ..options.clone()
+            },
+        )?;
+        tracing.restore(&mut envvars, &options.envs);
+        let records = trace::parse(kind, &stderr);
+        self.provenance = Some(provenance::build(
+            &trace::assignments(kind, &records),
+            &envvars,
+        ));
+        self.set_envvars(envvars);
+        Ok(())
+    }
+
+    /// Makes attempt to load variables of the shell itself: the shell is asked to
+    /// print own variables (`declare -p` for bash, `typeset -p` for zsh and ksh,
+    /// `set --show` for fish, `set` for csh, `Get-Variable` for PowerShell, `scope
+    /// variables` for nushell). Unlike `load` it includes variables, which aren't
+    /// exported, like `HISTFILE` or `fish_user_paths`. Environment variables of
+    /// PowerShell are named like `env:PATH`. Variables are saved in
+    /// `self.shellvars`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{path::PathBuf, str::FromStr};
+    /// use envvars::Profile;
+    ///
+    /// let mut profile: Profile = if cfg!(windows) {
+    ///     Profile::new(&PathBuf::from_str("C:\\Program Files\\Git\\bin\\bash.exe").unwrap(), vec!["-c"], None).unwrap()
+    /// } else {
+    ///     Profile::new(&PathBuf::from_str("/bin/bash").unwrap(), vec!["-c"], None).unwrap()
+    /// };
+    ///
+    /// profile.load_shellvars().unwrap();
+    ///
+    /// let vars = profile.shellvars.as_ref().unwrap();
+    /// assert!(vars.contains_key("BASH_VERSION"));
+    /// assert!(!vars["BASH_VERSION"].exported);
+    /// ```
+    pub fn load_shellvars(&mut self) -> Result<(), Error> {
+        DEFAULT.load_shellvars(self)
+    }
+
+    /// Same as `load_shellvars`, but uses the given options (see `Envvars`)
+    pub(crate) fn load_shellvars_by(&mut self, options: &Options) -> Result<(), Error> {
+        self.shellvars = Some(shellvars::load(
+            &self.path,
+            self.kind(),
+            &self.args,
+            options,
+        )?);
+        Ok(())
+    }
+
+    /// Makes attempt to load aliases of the shell (`alias` for bash, zsh, sh, ksh and
+    /// csh; `alias` and `abbr` for fish; `Get-Alias` for PowerShell; `scope aliases`
+    /// for nushell). Aliases are saved in `self.aliases` as name and expansion.
+    ///
+    /// Note, usually aliases are defined in startup files of interactive shells (like
+    /// `~/.bashrc`); the profile should be created with arguments of interactive
+    /// shell (like `-i`) to get them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{path::PathBuf, str::FromStr};
+    /// use envvars::Profile;
+    ///
+    /// let mut profile: Profile = if cfg!(windows) {
+    ///     Profile::new(&PathBuf::from_str("C:\\Program Files\\Git\\bin\\bash.exe").unwrap(), vec!["-i", "-c"], None).unwrap()
+    /// } else {
+    ///     Profile::new(&PathBuf::from_str("/bin/bash").unwrap(), vec!["-i", "-c"], None).unwrap()
+    /// };
+    ///
+    /// profile.load_aliases().unwrap();
+    ///
+    /// for (name, expansion) in profile.aliases.as_ref().unwrap() {
+    ///     println!("{name} => {expansion}");
+    /// }
+    /// ```
+    pub fn load_aliases(&mut self) -> Result<(), Error> {
+        DEFAULT.load_aliases(self)
+    }
+
+    /// Same as `load_aliases`, but uses the given options (see `Envvars`)
+    pub(crate) fn load_aliases_by(&mut self, options: &Options) -> Result<(), Error> {
+        self.aliases = Some(functions::aliases(
+            &self.path,
+            self.kind(),
+            &self.args,
+            options,
+        )?);
+        Ok(())
+    }
+
+    /// Returns environment variables of profile keeping list-typed variables as lists:
+    /// lists of fish, zsh arrays tied with scalars (`path` and `PATH`) and lists of
+    /// nushell's `$env`. Other variables are returned as `EnvValue::Scalar`. If
+    /// `envvars` or `shellvars` haven't been loaded yet, makes attempt to load it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{path::PathBuf, str::FromStr};
+    /// use envvars::{EnvValue, Profile};
+    ///
+    /// let mut profile: Profile = if cfg!(windows) {
+    ///     Profile::new(&PathBuf::from_str("C:\\Program Files\\Git\\bin\\bash.exe").unwrap(), vec!["-c"], None).unwrap()
+    /// } else {
+    ///     Profile::new(&PathBuf::from_str("/bin/bash").unwrap(), vec!["-c"], None).unwrap()
+    /// };
+    ///
+    /// let envvars = profile.typed_envvars().unwrap();
+    ///
+    /// // bash doesn't have list-typed environment variables
+    /// assert!(matches!(envvars.get("PATH"), Some(EnvValue::Scalar(_))));
+    /// ```
+    pub fn typed_envvars(&mut self) -> Result<HashMap<String, EnvValue>, Error> {
+        if self.shellvars.is_none() {
+            self.load_shellvars()?;
+        }
+        let envvars = self.loaded()?.clone();
+        let shellvars = self.shellvars.as_ref();
+        Ok(envvars
+            .into_iter()
+            .map(|(name, value)| {
+                let value = match shellvars.and_then(|vars| vars.get(&name)) {
+                    Some(var) if var.value.is_list() => var.value.clone(),
+                    _ => EnvValue::Scalar(value),
+                };
+                (name, value)
+            })
+            .collect())
+    }
+
+    /// Same as `load`, but measures time spent during the shell's initialization and
+    /// returns a breakdown of time per startup file and the slowest commands. Variables
+    /// are saved in `self.envvars`.
+    ///
+    /// Timestamps are reported by bash 5+ (`EPOCHREALTIME`), zsh and fish 3.2+
+    /// (`--profile-startup`). Fish doesn't report startup files, sh and ksh don't
+    /// report timestamps at all. Bash ignores the trace prompt running as root, in
+    /// this case only `total` is measured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{path::PathBuf, str::FromStr};
+    /// use envvars::Profile;
+    ///
+    /// let mut profile: Profile = if cfg!(windows) {
+    ///     Profile::new(&PathBuf::from_str("C:\\Program Files\\Git\\bin\\bash.exe").unwrap(), vec!["-c"], None).unwrap()
+    /// } else {
+    ///     Profile::new(&PathBuf::from_str("/bin/bash").unwrap(), vec!["-c"], None).unwrap()
+    /// };
+    ///
+    /// let report = profile.load_profiled().unwrap();
+    ///
+    /// for file in report.files.iter() {
+    ///     println!("{:?}: {:?}", file.file, file.duration);
+    /// }
+    /// assert!(profile.envvars.is_some());
+    /// ```
+    pub fn load_profiled(&mut self) -> Result<StartupProfile, Error> {
+        DEFAULT.load_profiled(self)
+    }
+
+    /// Same as `load_profiled`, but uses the given extractor and options (see
+    /// `Envvars`)
+    pub(crate) fn load_profiled_by(
+        &mut self,
+        extractor: &Mutex<Extractor>,
+        options: &Options,
+    ) -> Result<StartupProfile, Error> {
+        let kind = self.kind();
+        let tracing = trace::setup(kind, true)?;
+        let args = self.args_with(tracing.args.clone());
+        let epoch = || {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs_f64())
+                .unwrap_or_default()
+        };
+        let mut extractor = lock(extractor);
+        let started = (Instant::now(), epoch());
+        let result = extractor.get_with(
+            Some(&self.path),
+            &args,
+            &Options {
+                envs: [options.envs.clone(), tracing.envs.clone()].concat(),
+                ..options.clone()
+            },
+        );
+        let total = started.0.elapsed();
+        let finished = epoch();
+        let executable = extractor.executable();
+        drop(extractor);
+        let report = tracing.report.as_ref().map(|file| {
+            // Old versions of fish don't support profiling of startup
+            let content = fs::read_to_string(file).unwrap_or_else(|err| {
+                log::warn!("Fail to read report {file:?}: {err}");
+                String::new()
+            });
+            if file.exists() {
+                if let Err(err) = fs::remove_file(file) {
+                    log::warn!("Fail to remove report {file:?}: {err}");
+                }
+            }
+            content
+        });
+        let (mut envvars, stderr) = result?;
+        tracing.restore(&mut envvars, &options.envs);
+        let records = match report {
+            Some(content) => trace::parse_fish_report(started.1, &content),
+            None => trace::parse(kind, &stderr),
+        };
+        self.set_envvars(envvars);
+        Ok(profiling::build(&records, &executable, total, finished))
+    }
+
+    /// Explains where environment variables come from. The first layer has variables
+    /// of system files (`/etc/environment`, pam_env, `environment.d`; see
+    /// `LayerKind::System`). Then the shell runs in successive modes: without startup
+    /// files, as login shell, as interactive shell, as login and interactive shell and
+    /// (if `cwd` is defined) as login and interactive shell, which changes the folder
+    /// to `cwd` (see `LayerKind::Directory`). Each returned layer has all variables
+    /// and a difference with the previous layer (see `LayerKind::base`); system and
+    /// clean layers report all their variables as added.
+    ///
+    /// Supported for bash, zsh, fish, sh and ksh. Note, `self.args` aren't used.
+    /// * `cwd` - folder to detect changes done by folder-specific hooks
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{path::PathBuf, str::FromStr};
+    /// use envvars::{LayerKind, Profile};
+    ///
+    /// let profile: Profile = if cfg!(windows) {
+    ///     Profile::new(&PathBuf::from_str("C:\\Program Files\\Git\\bin\\bash.exe").unwrap(), vec!["-c"], None).unwrap()
+    /// } else {
+    ///     Profile::new(&PathBuf::from_str("/bin/bash").unwrap(), vec!["-c"], None).unwrap()
+    /// };
+    ///
+    /// let layers = profile.load_layers(None).unwrap();
+    ///
+    /// assert_eq!(layers[0].kind, LayerKind::System);
+    /// assert_eq!(layers[1].kind, LayerKind::Clean);
+    /// for layer in layers.iter() {
+    ///     println!("{:?}: added {:?}", layer.kind, layer.diff.added.keys());
+    /// }
+    /// ```
+    pub fn load_layers(&self, cwd: Option<&Path>) -> Result<Vec<Layer>, Error> {
+        DEFAULT.load_layers(self, cwd)
+    }
+
+    /// Same as `load_layers`, but uses the given extractor and options (see
+    /// `Envvars`)
+    pub(crate) fn load_layers_by(
+        &self,
+        cwd: Option<&Path>,
+        extractor: &Mutex<Extractor>,
+        options: &Options,
+    ) -> Result<Vec<Layer>, Error> {
+        layers::load(&self.path, self.kind(), cwd, extractor, options)
+    }
+
+    /// Detects exported variables by reading startup files of the shell (including
+    /// files loaded with `source`) without executing anything. Faster and safer than
+    /// `load`, but approximate: values produced by commands, unknown variables or
+    /// assignments inside conditions, loops and functions are returned as
+    /// `StaticValue::Unresolved`. `envvars` isn't changed.
+    ///
+    /// Returns `Error::NotSupportedShell` for shells without POSIX-like, fish or csh
+    /// syntax.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{path::PathBuf, str::FromStr};
+    /// use envvars::Profile;
+    ///
+    /// let profile: Profile = if cfg!(windows) {
+    ///     Profile::new(&PathBuf::from_str("C:\\Program Files\\Git\\bin\\bash.exe").unwrap(), vec!["-c"], None).unwrap()
+    /// } else {
+    ///     Profile::new(&PathBuf::from_str("/bin/bash").unwrap(), vec!["-c"], None).unwrap()
+    /// };
+    ///
+    /// for (name, var) in profile.load_static().unwrap() {
+    ///     println!("{name}: {:?} ({:?}:{})", var.value, var.file, var.line);
+    /// }
+    /// ```
+    pub fn load_static(&self) -> Result<HashMap<String, StaticVar>, Error> {
+        analyzer::analyze(self.kind(), self.mode())
+    }
+
+    /// Returns arguments of the shell with additional arguments. Long options (like
+    /// "--login") should go before short options, that's why additional arguments
+    /// are inserted after long options.
+    fn args_with(&self, extra: Vec<String>) -> Vec<String> {
+        let mut args = self.args.clone();
+        let position = args
+            .iter()
+            .position(|a| !a.starts_with("--"))
+            .unwrap_or(args.len());
+        args.splice(position..position, extra);
+        args
+    }
+
+    /// Same as `load`, but uses cache of environment variables (see `Cache`), which
+    /// keeps loaded variables in memory during 30 seconds. To keep variables on
+    /// disk between starts of the application, use own instance of cache created
+    /// with `Cache::persistent` or `Cache::new`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{path::PathBuf, str::FromStr};
+    /// use envvars::Profile;
+    ///
+    /// let mut profile: Profile = if cfg!(windows) {
+    ///     Profile::new(&PathBuf::from_str("C:\\Program Files\\Git\\bin\\bash.exe").unwrap(), vec!["-c"], None).unwrap()
+    /// } else {
+    ///     Profile::new(&PathBuf::from_str("/bin/bash").unwrap(), vec!["-c"], None).unwrap()
+    /// };
+    ///
+    /// profile.load_cached().unwrap();
+    ///
+    /// assert!(profile.envvars.is_some());
+    /// ```
+    pub fn load_cached(&mut self) -> Result<(), Error> {
+        CACHE.load(self)
+    }
+
+    /// Makes attempt to persist the variable: writes it into the startup file of the
+    /// user (`~/.profile` or `~/.bash_profile`, `~/.zprofile`, `config.fish`, `~/.login`,
+    /// PowerShell profile etc, depending on the kind of shell and the scope) using the
+    /// syntax of the shell (`export`, `set -gx`, `setenv`, `$env:`). The variable is
+    /// placed into a block managed by `envvars`, so repeated calls update the same
+    /// line. After writing, the shell is reloaded to check the variable has the
+    /// expected value; if not, the original content of the file is restored and
+    /// `Error::NotPersisted` is returned. Returns path to the changed file.
+    ///
+    /// * `name` - name of variable
+    /// * `value` - value of variable; cannot have line breaks
+    /// * `scope` - defines which startup file should be used
+    pub fn persist_var(
+        &self,
+        name: &str,
+        value: &str,
+        scope: PersistScope,
+    ) -> Result<PathBuf, Error> {
+        DEFAULT.persist_var(self, name, value, scope)
+    }
+
+    /// Removes the variable from the block managed by `envvars` in the startup file
+    /// (see `persist_var`). The block is removed as soon as it's empty. After writing,
+    /// the shell is reloaded to check the variable is gone (or has the value inherited
+    /// from the current process); if not, the original content of the file is
+    /// restored and `Error::NotPersisted` is returned. Returns path to the startup
+    /// file.
+    ///
+    /// * `name` - name of variable
+    /// * `scope` - defines which startup file should be used
+    pub fn unpersist_var(&self, name: &str, scope: PersistScope) -> Result<PathBuf, Error> {
+        DEFAULT.unpersist_var(self, name, scope)
+    }
+
+    /// Same as `persist_var` and `unpersist_var` (if `value` is `None`), but verifies
+    /// the result with the given extractor and options (see `Envvars`)
+    pub(crate) fn persist_var_by(
+        &self,
+        name: &str,
+        value: Option<&str>,
+        scope: PersistScope,
+        extractor: &Mutex<Extractor>,
+        options: &Options,
+    ) -> Result<PathBuf, Error> {
+        persist::persist(self, name, value, scope, extractor, options)
+    }
+
+    /// Returns startup files, which the shell would read in the given mode, in order
+    /// of reading: system-wide files, `/etc/profile.d`, files of the user (`ZDOTDIR`
+    /// is considered for zsh, `XDG_CONFIG_HOME` and `conf.d` folders for fish). The
+    /// list includes candidates, which don't exist or are skipped by the shell; check
+    /// `StartupFile::read` to find files, which are actually read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::{path::PathBuf, str::FromStr};
+    /// use envvars::{Mode, Profile};
+    ///
+    /// let profile: Profile = if cfg!(windows) {
+    ///     Profile::new(&PathBuf::from_str("C:\\Program Files\\Git\\bin\\bash.exe").unwrap(), vec!["-c"], None).unwrap()
+    /// } else {
+    ///     Profile::new(&PathBuf::from_str("/bin/bash").unwrap(), vec!["-c"], None).unwrap()
+    /// };
+    ///
+    /// let files = profile.startup_files(Mode { login: true, interactive: false });
+    ///
+    /// for file in files.iter().filter(|f| f.read) {
+    ///     println!("{:?}: {:?} bytes", file.path, file.size);
+    /// }
+    /// ```
+    pub fn startup_files(&self, mode: Mode) -> Vec<StartupFile> {
+        startup::files(self.kind(), mode)
+    }
+
+    /// Returns kind of shell detected by name of shell's executable file
+    pub fn kind(&self) -> ShellKind {
+        ShellKind::from_path(&self.path)
+    }
+
+    /// Returns mode in which shell is started to extract environment variables
+    pub fn mode(&self) -> Mode {
+        Mode::from_args(&self.args)
+    }
+
+    /// Returns arguments used to execute shell
+    pub(crate) fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Saves environment variables, exported functions of bash are moved into
+    /// `self.functions`
+    pub(crate) fn set_envvars(&mut self, envvars: HashMap<String, String>) {
+        let (envvars, functions) = functions::split(envvars);
+        self.envvars = Some(envvars);
+        self.functions = Some(functions);
+    }
+
+    /// Returns environment variables as they were reported by the shell: with
+    /// exported functions of bash
+    pub(crate) fn raw_envvars(&self) -> Option<HashMap<String, String>> {
+        let mut envvars = self.envvars.clone()?;
+        if let Some(functions) = self.functions.as_ref() {
+            envvars.extend(functions::encode(functions));
+        }
+        Some(envvars)
+    }
+
+    /// Returns environment variables of profile. If variables haven't been loaded
+    /// yet, makes attempt to load it.
+    pub(crate) fn loaded(&mut self) -> Result<&HashMap<String, String>, Error> {
+        if self.envvars.is_none() {
+            self.load()?;
+        }
+        self.envvars
+            .as_ref()
+            .ok_or_else(|| Error::NotLoaded(self.path.clone()))
+    }
 }
 
 /// Returns all detected shell's profiles.
@@ -140,3 +728,47 @@ pub fn get() -> Result<Vec<Profile>, Error> {
         Err(Error::NotSupportedPlatform)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn bash() -> Profile {
+        Profile::new(&PathBuf::from("/bin/bash"), vec!["-c"], None).expect("Profile of bash")
+    }
+
+    /// Variables, which enable tracing, aren't reported as variables of the shell
+    fn assert_tracing_vars_absent(profile: &Profile) {
+        let envvars = profile.envvars.as_ref().expect("Envvars should be loaded");
+        for key in ["PS4", "BASH_XTRACEFD", "fish_trace"] {
+            assert_eq!(envvars.get(key), env::var(key).ok().as_ref(), "{key}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn traced() {
+        let mut profile = bash();
+        profile.load_traced().expect("Envvars should be traced");
+        assert_tracing_vars_absent(&profile);
+        let provenance = profile
+            .provenance
+            .as_ref()
+            .expect("Provenance should be built");
+        assert!(!provenance.variables.contains_key("BASH_XTRACEFD"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn profiled() {
+        let mut profile = bash();
+        let report = profile.load_profiled().expect("Startup should be profiled");
+        assert_tracing_vars_absent(&profile);
+        let executable = lock(&crate::EXTRACTOR).executable();
+        assert!(!report
+            .commands
+            .iter()
+            .any(|c| Path::new(&c.command) == executable));
+    }
+}
@@ -0,0 +1,253 @@
+use super::kind::{Mode, ShellKind};
+use home::home_dir;
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    env, fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Startup (configuration) file of the shell
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct StartupFile {
+    /// Path to file
+    pub path: PathBuf,
+    /// True if the shell would read the file in the given mode. It's false for
+    /// files, which don't exist, are skipped because of another file (like
+    /// `~/.profile` if `~/.bash_profile` exists), or aren't read by the shell
+    /// directly but usually are loaded from other startup files (like `~/.bashrc`
+    /// from `~/.bash_profile`).
+    pub read: bool,
+    /// True if file exists
+    pub exists: bool,
+    /// Size of file in bytes
+    pub size: Option<u64>,
+    /// Time of last modification of file
+    pub modified: Option<SystemTime>,
+}
+
+impl StartupFile {
+    fn new(path: PathBuf, read: bool) -> Self {
+        let metadata = fs::metadata(&path).ok().filter(|m| m.is_file());
+        StartupFile {
+            read: read && metadata.is_some(),
+            exists: metadata.is_some(),
+            size: metadata.as_ref().map(|m| m.len()),
+            modified: metadata.and_then(|m| m.modified().ok()),
+            path,
+        }
+    }
+}
+
+/// Ordered list of startup files
+#[derive(Default)]
+struct Files(Vec<StartupFile>);
+
+impl Files {
+    /// Adds a file, which is always read by the shell if it exists
+    fn add(&mut self, path: PathBuf) {
+        self.0.push(StartupFile::new(path, true));
+    }
+
+    /// Adds a file, which isn't read by the shell directly
+    fn add_unread(&mut self, path: PathBuf) {
+        self.0.push(StartupFile::new(path, false));
+    }
+
+    /// Adds files; only the first existing file is read by the shell
+    fn add_first(&mut self, paths: Vec<PathBuf>) {
+        let mut found = false;
+        for path in paths {
+            let file = StartupFile::new(path, !found);
+            found = found || file.exists;
+            self.0.push(file);
+        }
+    }
+
+    /// Adds files of `/etc/profile.d`. These files are read by `/etc/profile`, if it
+    /// refers to this folder.
+    fn add_profile_d(&mut self) {
+        let read = fs::read_to_string("/etc/profile")
+            .map(|content| content.contains("/etc/profile.d"))
+            .unwrap_or(false);
+        for path in sorted(Path::new("/etc/profile.d"), "sh") {
+            self.0.push(StartupFile::new(path, read));
+        }
+    }
+}
+
+/// Returns files of the folder with the given extension sorted by name
+fn sorted(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut paths = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().map(|e| e == extension).unwrap_or(false))
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+    paths
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+fn config_dir(home: &Path) -> PathBuf {
+    env_path("XDG_CONFIG_HOME").unwrap_or_else(|| home.join(".config"))
+}
+
+/// Returns startup files of fish: `conf.d` snippets (a file of the user overrides
+/// files with the same name in system folders), then `config.fish` files
+fn fish(files: &mut Files, home: &Path) {
+    let config = config_dir(home).join("fish");
+    let mut dirs = vec![config.join("conf.d"), PathBuf::from("/etc/fish/conf.d")];
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or("/usr/local/share:/usr/share".to_owned());
+    for dir in data_dirs.split(':').filter(|dir| !dir.is_empty()) {
+        dirs.push(Path::new(dir).join("fish").join("vendor_conf.d"));
+    }
+    let mut names: HashSet<PathBuf> = HashSet::new();
+    let mut snippets: Vec<(PathBuf, PathBuf, bool)> = vec![];
+    for dir in dirs.iter() {
+        for path in sorted(dir, "fish") {
+            let name = PathBuf::from(path.file_name().unwrap_or_default());
+            let read = names.insert(name.clone());
+            snippets.push((name, path, read));
+        }
+    }
+    snippets.sort_by(|a, b| a.0.cmp(&b.0));
+    for (_, path, read) in snippets {
+        files.0.push(StartupFile::new(path, read));
+    }
+    files.add(PathBuf::from("/etc/fish/config.fish"));
+    files.add(config.join("config.fish"));
+}
+
+/// Returns startup files, which could be read by shell in the given mode, in order
+/// of reading. The list includes files, which don't exist.
+pub(crate) fn files(kind: ShellKind, mode: Mode) -> Vec<StartupFile> {
+    let home = home_dir().unwrap_or_default();
+    let mut files = Files::default();
+    match kind {
+        ShellKind::Bash => {
+            if mode.login {
+                files.add(PathBuf::from("/etc/profile"));
+                files.add_profile_d();
+                files.add_first(vec![
+                    home.join(".bash_profile"),
+                    home.join(".bash_login"),
+                    home.join(".profile"),
+                ]);
+                if mode.interactive {
+                    files.add_unread(home.join(".bashrc"));
+                }
+            } else if mode.interactive {
+                files.add(PathBuf::from("/etc/bash.bashrc"));
+                files.add(home.join(".bashrc"));
+            }
+        }
+        ShellKind::Zsh => {
+            let global = if Path::new("/etc/zsh").is_dir() {
+                PathBuf::from("/etc/zsh")
+            } else {
+                PathBuf::from("/etc")
+            };
+            let dot = env_path("ZDOTDIR").unwrap_or(home.clone());
+            files.add(global.join("zshenv"));
+            files.add(dot.join(".zshenv"));
+            if mode.login {
+                files.add(global.join("zprofile"));
+                files.add(dot.join(".zprofile"));
+            }
+            if mode.interactive {
+                files.add(global.join("zshrc"));
+                files.add(dot.join(".zshrc"));
+            }
+            if mode.login {
+                files.add(global.join("zlogin"));
+                files.add(dot.join(".zlogin"));
+            }
+        }
+        ShellKind::Fish => fish(&mut files, &home),
+        ShellKind::Sh | ShellKind::Ksh => {
+            if mode.login {
+                files.add(PathBuf::from("/etc/profile"));
+                files.add_profile_d();
+                files.add(home.join(".profile"));
+            }
+            if mode.interactive {
+                match env_path("ENV") {
+                    Some(path) => files.add(path),
+                    None if kind == ShellKind::Ksh => files.add(home.join(".kshrc")),
+                    None => {}
+                }
+            }
+        }
+        ShellKind::Csh => {
+            files.add(PathBuf::from("/etc/csh.cshrc"));
+            files.add_first(vec![home.join(".tcshrc"), home.join(".cshrc")]);
+            if mode.login {
+                files.add(PathBuf::from("/etc/csh.login"));
+                files.add(home.join(".login"));
+            }
+        }
+        ShellKind::Nu => {
+            let config = config_dir(&home).join("nushell");
+            files.add(config.join("env.nu"));
+            files.add(config.join("config.nu"));
+            if mode.login {
+                files.add(config.join("login.nu"));
+            }
+        }
+        ShellKind::PowerShell => {
+            let documents = home.join("Documents");
+            files.add(
+                documents
+                    .join("PowerShell")
+                    .join("Microsoft.PowerShell_profile.ps1"),
+            );
+            files.add(
+                documents
+                    .join("WindowsPowerShell")
+                    .join("Microsoft.PowerShell_profile.ps1"),
+            );
+        }
+        ShellKind::Cmd | ShellKind::Unknown => {}
+    }
+    files.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test() {
+        let temp = TempDir::new("startup");
+        let dir = temp.path();
+        fs::write(dir.join(".bash_login"), "export A=1\n").expect("File should be written");
+        fs::write(dir.join(".profile"), "export B=1\n").expect("File should be written");
+        let mut list = Files::default();
+        list.add_first(vec![
+            dir.join(".bash_profile"),
+            dir.join(".bash_login"),
+            dir.join(".profile"),
+        ]);
+        let read = list
+            .0
+            .iter()
+            .map(|f| (f.exists, f.read))
+            .collect::<Vec<(bool, bool)>>();
+        assert_eq!(read, vec![(false, false), (true, true), (true, false)]);
+        assert_eq!(list.0[1].size, Some(11));
+        let bash = files(ShellKind::Bash, Mode::default());
+        assert!(bash.is_empty());
+    }
+}
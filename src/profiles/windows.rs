@@ -1,4 +1,4 @@
-use crate::{profiles::Profile, Error, EXTRACTOR};
+use crate::{extractor::lock, profiles::Profile, Error, EXTRACTOR};
 use home::home_dir;
 use std::{
     collections::HashMap,
@@ -13,11 +13,7 @@ const PROCESSOR_ARCHITEW6432: &str = "processor_architew6432";
 const HOMEDRIVE: &str = "homedrive";
 
 fn get_envvars() -> Result<HashMap<String, String>, Error> {
-    let envvars = match EXTRACTOR
-        .lock()
-        .map_err(|e| Error::PoisonError(e.to_string()))?
-        .get(None, &Vec::new())
-    {
+    let envvars = match lock(&EXTRACTOR).get(None, &Vec::new()) {
         Ok(vars) => vars,
         Err(err) => {
             log::warn!("Fail to get envvars with extractor: {err}");
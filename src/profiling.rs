@@ -0,0 +1,160 @@
+use crate::{syntax::words, trace::Record};
+use serde::Serialize;
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Max number of commands in `StartupProfile::commands`
+const SLOWEST: usize = 20;
+
+/// Time spent in a startup file
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileTiming {
+    /// Startup file. `None` collects commands, for which the shell doesn't report
+    /// a file
+    pub file: Option<PathBuf>,
+    /// Own time of commands of the file. Time of sourced files isn't included.
+    pub duration: Duration,
+    /// Number of traced commands
+    pub commands: usize,
+}
+
+/// Time spent in a single command
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CommandTiming {
+    /// Startup file, which has the command, if the shell reports it
+    pub file: Option<PathBuf>,
+    /// Line in startup file, if the shell reports it
+    pub line: Option<usize>,
+    /// Traced command
+    pub command: String,
+    /// Time between the start of the command and the start of the next traced command
+    pub duration: Duration,
+}
+
+/// Breakdown of time spent during the shell's initialization
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct StartupProfile {
+    /// Total time of execution of the shell including extracting of variables
+    pub total: Duration,
+    /// Time spent per startup file, the slowest file goes first
+    pub files: Vec<FileTiming>,
+    /// The slowest commands (up to 20), the slowest command goes first
+    pub commands: Vec<CommandTiming>,
+}
+
+/// Checks the record is the start of the extractor, which isn't a part of startup
+fn is_extractor(record: &Record, executable: &Path) -> bool {
+    words(&record.command)
+        .first()
+        .is_some_and(|command| Path::new(command) == executable)
+}
+
+/// Builds a profile of startup from trace records. Duration of each record is the
+/// time until the next record; duration of the last record is the time until the
+/// shell has been finished. The start of the extractor ends the previous record, but
+/// isn't counted itself.
+/// * `executable` - path, which the extractor is started with
+/// * `finished` - time (seconds since UNIX epoch) when the shell has been finished
+pub(crate) fn build(
+    records: &[Record],
+    executable: &Path,
+    total: Duration,
+    finished: f64,
+) -> StartupProfile {
+    let timed = records
+        .iter()
+        .filter_map(|r| r.time.map(|t| (t, r)))
+        .collect::<Vec<(f64, &Record)>>();
+    let mut commands: Vec<CommandTiming> = vec![];
+    let mut files: HashMap<Option<PathBuf>, (Duration, usize)> = HashMap::new();
+    for (i, (time, record)) in timed.iter().enumerate() {
+        if is_extractor(record, executable) {
+            continue;
+        }
+        let next = timed.get(i + 1).map(|(t, _)| *t).unwrap_or(finished);
+        let duration = Duration::from_secs_f64((next - time).max(0.0));
+        let file = files.entry(record.file.clone()).or_default();
+        file.0 += duration;
+        file.1 += 1;
+        commands.push(CommandTiming {
+            file: record.file.clone(),
+            line: record.line,
+            command: record.command.clone(),
+            duration,
+        });
+    }
+    commands.sort_by_key(|c| Reverse(c.duration));
+    commands.truncate(SLOWEST);
+    let mut files = files
+        .into_iter()
+        .map(|(file, (duration, commands))| FileTiming {
+            file,
+            duration,
+            commands,
+        })
+        .collect::<Vec<FileTiming>>();
+    files.sort_by_key(|f| Reverse(f.duration));
+    StartupProfile {
+        total,
+        files,
+        commands,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{trace, ShellKind};
+
+    #[test]
+    fn test() {
+        let stderr = [
+            "+ENVVARS|10.0|/etc/profile|1| PATH=/bin",
+            "+ENVVARS|10.25|/etc/profile|2| . /home/user/.profile",
+            "++ENVVARS|10.25|/home/user/.profile|1| sleep 2",
+            "++ENVVARS|12.25|/home/user/.profile|2| export A=1",
+            "+ENVVARS|12.5|/etc/profile|3| unset i",
+        ]
+        .join("\n");
+        let records = trace::parse(ShellKind::Bash, &stderr);
+        let profile = build(
+            &records,
+            Path::new("/tmp/extractor"),
+            Duration::from_secs(3),
+            13.0,
+        );
+        assert_eq!(profile.files.len(), 2);
+        assert_eq!(
+            profile.files[0].file,
+            Some(PathBuf::from("/home/user/.profile"))
+        );
+        assert_eq!(profile.files[0].commands, 2);
+        assert_eq!(profile.files[1].duration.as_millis(), 750);
+        assert_eq!(profile.commands[0].command, "sleep 2");
+        assert_eq!(profile.commands[0].duration.as_millis(), 2000);
+    }
+
+    #[test]
+    fn extractor() {
+        let stderr = [
+            "+ENVVARS|10.0|/etc/profile|1| sleep 1",
+            "+ENVVARS|11.0||1| /proc/42/fd/3",
+        ]
+        .join("\n");
+        let records = trace::parse(ShellKind::Bash, &stderr);
+        let profile = build(
+            &records,
+            Path::new("/proc/42/fd/3"),
+            Duration::from_secs(5),
+            15.0,
+        );
+        assert_eq!(profile.commands.len(), 1);
+        assert_eq!(profile.commands[0].command, "sleep 1");
+        assert_eq!(profile.commands[0].duration.as_millis(), 1000);
+        assert_eq!(profile.files.len(), 1);
+    }
+}
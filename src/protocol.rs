@@ -0,0 +1,204 @@
+use crate::{assets, context::ShellContext, error::Diagnostics, extractor::Options, Error};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    process::{self, Output},
+    str::from_utf8,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Latest version of protocol between the library and the extractor. Version 1 is
+/// a bare JSON object with environment variables.
+pub(crate) const PROTOCOL: u32 = 2;
+
+/// Prefix of the line with the envelope
+const MARKER: &str = "ENVVARS_ENVELOPE|";
+
+/// Variable with version of protocol requested by the library
+const PROTOCOL_VAR: &str = "ENVVARS_PROTOCOL";
+
+/// Variable with random string, which the extractor returns in the envelope
+const NONCE_VAR: &str = "ENVVARS_NONCE";
+
+/// Variable, which asks the extractor to report the context of the process
+const CONTEXT_VAR: &str = "ENVVARS_CONTEXT";
+
+/// Counter to make nonces unique inside of the process
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Data reported by the extractor
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Payload {
+    pub envvars: HashMap<String, String>,
+    pub context: Option<ShellContext>,
+}
+
+/// Response of the extractor (protocol 2+)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Envelope {
+    /// Version of protocol used by the extractor
+    pub protocol: u32,
+    /// Checksum of the extractor's executable file
+    pub checksum: Option<String>,
+    /// pid of the extractor
+    pub pid: u32,
+    /// pid of parent of the extractor (unix only)
+    pub ppid: Option<u32>,
+    /// Time of creating the envelope, seconds since UNIX epoch
+    pub timestamp: f64,
+    /// Nonce sent by the library
+    pub nonce: Option<String>,
+    pub payload: Payload,
+}
+
+/// Request to the extractor
+pub(crate) struct Request {
+    nonce: String,
+}
+
+impl Request {
+    /// Creates a request and adds variables of the request to options of running
+    /// the shell
+    pub fn new(options: &mut Options, context: bool) -> Self {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&process::id().to_le_bytes());
+        hasher.update(&COUNTER.fetch_add(1, Ordering::SeqCst).to_le_bytes());
+        hasher.update(
+            &SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default()
+                .to_le_bytes(),
+        );
+        let nonce = hasher.finalize().to_hex()[..32].to_owned();
+        options
+            .envs
+            .push((PROTOCOL_VAR.to_owned(), PROTOCOL.to_string()));
+        options.envs.push((NONCE_VAR.to_owned(), nonce.clone()));
+        if context {
+            options
+                .envs
+                .push((CONTEXT_VAR.to_owned(), String::from("1")));
+        }
+        Request { nonce }
+    }
+
+    /// Finds and validates the envelope in stdout of the shell. Lines, which
+    /// aren't envelopes with expected nonce, are ignored: startup files can print
+    /// anything. If the envelope isn't found, stdout is parsed as protocol 1.
+    ///
+    /// * `output` - output of the shell
+    /// * `child` - pid of the shell
+    pub fn parse(&self, output: &Output, child: u32) -> Result<Envelope, Error> {
+        let stdout = from_utf8(&output.stdout).map_err(Error::Decoding)?;
+        let envelope = stdout
+            .lines()
+            .rev()
+            .filter_map(|line| line.strip_prefix(MARKER))
+            .filter_map(|line| serde_json::from_str::<Envelope>(line).ok())
+            .find(|envelope| envelope.nonce.as_deref() == Some(self.nonce.as_str()));
+        let Some(envelope) = envelope else {
+            if stdout.contains(MARKER) {
+                return Err(Error::Protocol {
+                    message: String::from("envelope with expected nonce isn't found"),
+                    diagnostics: Box::new(Diagnostics::default().with_output(output)),
+                });
+            }
+            // Extractor of protocol 1 prints a bare JSON object
+            let envvars =
+                serde_json::from_str::<HashMap<String, String>>(stdout).map_err(|source| {
+                    Error::Parsing {
+                        source,
+                        diagnostics: Box::new(Diagnostics::default().with_output(output)),
+                    }
+                })?;
+            log::warn!("Extractor uses protocol 1");
+            return Ok(Envelope {
+                protocol: 1,
+                checksum: None,
+                pid: child,
+                ppid: None,
+                timestamp: 0.0,
+                nonce: None,
+                payload: Payload {
+                    envvars,
+                    context: None,
+                },
+            });
+        };
+        if envelope.protocol > PROTOCOL || envelope.protocol < 2 {
+            return Err(Error::Protocol {
+                message: format!("unsupported version of protocol: {}", envelope.protocol),
+                diagnostics: Box::new(Diagnostics::default().with_output(output)),
+            });
+        }
+        match envelope.checksum.as_deref() {
+            Some(checksum) if checksum != assets::checksum() => {
+                return Err(Error::Protocol {
+                    message: String::from("checksum of extractor doesn't match"),
+                    diagnostics: Box::new(Diagnostics::default().with_output(output)),
+                });
+            }
+            None => log::warn!("Extractor didn't report own checksum"),
+            _ => {}
+        }
+        // The shell can run the extractor directly (exec) or as a child process
+        if envelope.pid != child && envelope.ppid.map(|p| p != child).unwrap_or(false) {
+            log::warn!(
+                "Extractor (pid: {}, ppid: {:?}) isn't started by the shell (pid: {child})",
+                envelope.pid,
+                envelope.ppid
+            );
+        }
+        Ok(envelope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::ExitStatus;
+
+    #[cfg(unix)]
+    use std::os::unix::process::ExitStatusExt;
+    #[cfg(windows)]
+    use std::os::windows::process::ExitStatusExt;
+
+    #[test]
+    fn test() {
+        let mut options = Options::default();
+        let request = Request::new(&mut options, false);
+        let output = |stdout: String| Output {
+            status: ExitStatus::from_raw(0),
+            stdout: stdout.into_bytes(),
+            stderr: vec![],
+        };
+        let envelope = |nonce: &str, protocol: u32| {
+            format!(
+                "{MARKER}{{\"protocol\":{protocol},\"checksum\":\"{}\",\"pid\":10,\"ppid\":1,\
+                 \"timestamp\":1.5,\"nonce\":\"{nonce}\",\"payload\":{{\"envvars\":{{\"A\":\"1\"}},\
+                 \"context\":null}}}}",
+                assets::checksum()
+            )
+        };
+        let valid = envelope(&request.nonce, 2);
+        let forged = envelope("forged", 2);
+        let parsed = request
+            .parse(&output(format!("rc output\n{forged}\n{valid}\n")), 10)
+            .expect("Envelope should be parsed");
+        assert_eq!(parsed.payload.envvars["A"], "1");
+        assert!(matches!(
+            request.parse(&output(format!("{forged}\n")), 10),
+            Err(Error::Protocol { .. })
+        ));
+        assert!(matches!(
+            request.parse(&output(envelope(&request.nonce, PROTOCOL + 1)), 10),
+            Err(Error::Protocol { .. })
+        ));
+        let legacy = request
+            .parse(&output(String::from("{\"A\":\"1\"}")), 10)
+            .expect("Protocol 1 should be parsed");
+        assert_eq!(legacy.protocol, 1);
+    }
+}
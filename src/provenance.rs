@@ -0,0 +1,85 @@
+use crate::{merge::is_path_list, trace::Assignment};
+use serde::Serialize;
+use std::{collections::HashMap, env, path::PathBuf};
+
+/// Origin of environment variable or of an entry of a list of paths
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    /// Variable isn't changed by the shell, but inherited from the environment of
+    /// the parent process
+    Inherited,
+    /// Variable is set during the shell's initialization.
+    /// * `file` - startup file, which has the last assignment of variable. `None` if
+    ///   the shell doesn't report it
+    /// * `line` - line in startup file. `None` if the shell doesn't report it
+    Startup {
+        file: Option<PathBuf>,
+        line: Option<usize>,
+    },
+}
+
+impl From<&Assignment> for Origin {
+    fn from(assignment: &Assignment) -> Self {
+        Origin::Startup {
+            file: assignment.file.clone(),
+            line: assignment.line,
+        }
+    }
+}
+
+/// Describes where environment variables of the profile come from
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    /// Origin of each exported variable
+    pub variables: HashMap<String, Origin>,
+    /// Origin of each entry of variables, which are lists of paths (like `PATH`).
+    /// Entries are in the same order as in the variable.
+    pub segments: HashMap<String, Vec<(String, Origin)>>,
+}
+
+fn segments(name: &str, value: &str, assignments: &[Assignment]) -> Vec<(String, Origin)> {
+    let mut origins: HashMap<PathBuf, Origin> = HashMap::new();
+    for assignment in assignments.iter().filter(|a| a.name == name) {
+        let Some(value) = assignment.value.as_ref() else {
+            continue;
+        };
+        let mut next: HashMap<PathBuf, Origin> = HashMap::new();
+        for segment in env::split_paths(value) {
+            let origin = origins
+                .get(&segment)
+                .cloned()
+                .unwrap_or_else(|| assignment.into());
+            next.insert(segment, origin);
+        }
+        origins = next;
+    }
+    env::split_paths(value)
+        .map(|segment| {
+            let origin = origins.get(&segment).cloned().unwrap_or(Origin::Inherited);
+            (segment.to_string_lossy().to_string(), origin)
+        })
+        .collect()
+}
+
+/// Attributes each variable to the last assignment. If the trace has a few
+/// assignments (for example, some were done in subshells), the last assignment
+/// with the actual value is preferred.
+pub(crate) fn build(assignments: &[Assignment], envvars: &HashMap<String, String>) -> Provenance {
+    let mut provenance = Provenance::default();
+    for (name, value) in envvars.iter() {
+        let mut related = assignments.iter().rev().filter(|a| &a.name == name);
+        let origin = related
+            .clone()
+            .find(|a| a.value.as_ref() == Some(value))
+            .or_else(|| related.next())
+            .map(Origin::from)
+            .unwrap_or(Origin::Inherited);
+        provenance.variables.insert(name.clone(), origin);
+        if is_path_list(name) {
+            provenance
+                .segments
+                .insert(name.clone(), segments(name, value, assignments));
+        }
+    }
+    provenance
+}
@@ -0,0 +1,630 @@
+use crate::{
+    error::{truncate, Diagnostics},
+    extractor::Options,
+    merge::is_path_list,
+    syntax::{is_name, words},
+    EnvValue, Error, ShellKind,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::PathBuf,
+    process::{Command, Stdio},
+    str::from_utf8,
+    time::Instant,
+};
+
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+
+/// Line printed by the shell before the list of variables. Everything before it is
+/// an output of startup files.
+pub(crate) const MARKER: &str = "ENVVARS|VARS";
+
+/// Commands, which declare variables in output of `declare -p` / `typeset -p`
+const DECLARATIONS: [&str; 7] = [
+    "declare", "typeset", "export", "local", "readonly", "integer", "float",
+];
+
+/// Scope of shell variable
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VarScope {
+    /// Global variable of the shell
+    Global,
+    /// Universal variable, which is shared between all instances of the shell
+    /// (fish only)
+    Universal,
+    /// Variable, which is local for a function or a block
+    Local,
+}
+
+/// Variable of the shell itself. Unlike environment variables it can be not
+/// exported, like `HISTFILE` or fish's `fish_user_paths`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShellVar {
+    /// Value of variable. Arrays of bash, zsh and csh, lists of fish and nushell are
+    /// kept as `EnvValue::List`.
+    pub value: EnvValue,
+    /// Scope of variable
+    pub scope: VarScope,
+    /// true - if variable is exported into environment of child processes
+    pub exported: bool,
+}
+
+/// Returns a command, which prints all variables of the shell
+fn script(kind: ShellKind) -> Result<String, Error> {
+    Ok(match kind {
+        ShellKind::Bash => format!("printf '%s\\n' '{MARKER}'; declare -p"),
+        ShellKind::Zsh | ShellKind::Ksh => format!("printf '%s\\n' '{MARKER}'; typeset -p"),
+        // POSIX shells don't have `declare`: all variables, then exported ones
+        ShellKind::Sh => format!("printf '%s\\n' '{MARKER}'; set; export -p"),
+        ShellKind::Fish => format!("echo '{MARKER}'; set --show"),
+        ShellKind::Csh => format!("echo '{MARKER}'; set"),
+        // `$env` has exported variables only, `scope variables` has the rest. Values
+        // are converted one by one: closures cannot be converted into JSON.
+        ShellKind::Nu => format!(
+            "print '{MARKER}'; {{ env: $env, vars: (scope variables | each {{|v| \
+             {{ name: $v.name, value: (try {{ $v.value | to json --raw }} catch {{ 'null' }}) }} }}) }} \
+             | to json --raw"
+        ),
+        // Values are converted into JSON one by one: arrays as lists of strings,
+        // everything else as a string
+        ShellKind::PowerShell => format!(
+            "Write-Output '{MARKER}'; ConvertTo-Json -Compress -InputObject @(\
+             Get-Variable | ForEach-Object {{ $v = $_.Value; \
+             $json = if ($v -is [System.Collections.IList]) {{ \
+             ConvertTo-Json -Compress -InputObject @($v | ForEach-Object {{ \"$_\" }}) }} \
+             else {{ ConvertTo-Json -Compress -InputObject \"$v\" }}; \
+             [PSCustomObject]@{{ Name = $_.Name; Value = $json; Exported = $false }} }}; \
+             Get-ChildItem env: | ForEach-Object {{ [PSCustomObject]@{{ Name = \"env:$($_.Name)\"; \
+             Value = (ConvertTo-Json -Compress -InputObject $_.Value); Exported = $true }} }})"
+        ),
+        _ => return Err(Error::NotSupportedShell(format!("{kind:?}"))),
+    })
+}
+
+/// Parses output of `declare -p` (bash), `typeset -p` (zsh, ksh) and `set` with
+/// `export -p` (POSIX sh)
+fn parse_declarations(output: &str) -> HashMap<String, ShellVar> {
+    let tokens = words(output);
+    let mut vars: HashMap<String, ShellVar> = HashMap::new();
+    let mut pos = 0;
+    while pos < tokens.len() {
+        if !DECLARATIONS.contains(&tokens[pos].as_str()) {
+            // Output of `set` has assignments only
+            if let Some((name, value)) = tokens[pos].split_once('=').filter(|(n, _)| is_name(n)) {
+                vars.insert(
+                    name.to_owned(),
+                    ShellVar {
+                        value: EnvValue::from(value),
+                        scope: VarScope::Global,
+                        exported: false,
+                    },
+                );
+            }
+            pos += 1;
+            continue;
+        }
+        let command = tokens[pos].as_str();
+        pos += 1;
+        let mut flags = String::new();
+        while pos < tokens.len() && (tokens[pos].starts_with('-') || tokens[pos].starts_with('+')) {
+            flags.push_str(&tokens[pos]);
+            pos += 1;
+        }
+        let exported = command == "export" || flags.contains('x');
+        let scope = if command == "local" {
+            VarScope::Local
+        } else {
+            VarScope::Global
+        };
+        let mut names: Vec<String> = vec![];
+        while pos < tokens.len() && !DECLARATIONS.contains(&tokens[pos].as_str()) {
+            let token = &tokens[pos];
+            pos += 1;
+            let Some((name, value)) = token.split_once('=') else {
+                names.push(token.to_owned());
+                continue;
+            };
+            let value = if let Some(first) = value.strip_prefix('(') {
+                // Array: bash prints `([0]="a" [1]="b")`, zsh prints `( a b )`
+                let mut elements: Vec<String> = vec![];
+                let mut element = first.to_owned();
+                loop {
+                    let last = element.ends_with(')')
+                        && tokens
+                            .get(pos)
+                            .map(|next| !next.starts_with('['))
+                            .unwrap_or(true);
+                    if last {
+                        element.pop();
+                    }
+                    if !element.is_empty() {
+                        let element = match element.split_once("]=") {
+                            Some((key, value)) if element.starts_with('[') => {
+                                if flags.contains('A') {
+                                    format!("{}={value}", &key[1..])
+                                } else {
+                                    value.to_owned()
+                                }
+                            }
+                            _ => element,
+                        };
+                        elements.push(element);
+                    }
+                    if last || pos >= tokens.len() {
+                        break;
+                    }
+                    element = tokens[pos].to_owned();
+                    pos += 1;
+                }
+                // zsh ties scalar and array: `typeset -T PATH path=( ... )`
+                if flags.contains('T') {
+                    for scalar in names.drain(..) {
+                        vars.insert(
+                            scalar,
+                            ShellVar {
+                                value: EnvValue::List(elements.clone()),
+                                scope,
+                                exported,
+                            },
+                        );
+                    }
+                }
+                EnvValue::List(elements)
+            } else {
+                EnvValue::from(value)
+            };
+            vars.insert(
+                name.to_owned(),
+                ShellVar {
+                    value,
+                    scope,
+                    exported,
+                },
+            );
+        }
+        // Declared variables without value
+        for name in names {
+            vars.entry(name)
+                .and_modify(|var| var.exported = var.exported || exported)
+                .or_insert(ShellVar {
+                    value: EnvValue::default(),
+                    scope,
+                    exported,
+                });
+        }
+    }
+    vars
+}
+
+/// Parses output of `set --show` (fish)
+fn parse_fish(output: &str) -> HashMap<String, ShellVar> {
+    let mut vars: HashMap<String, ShellVar> = HashMap::new();
+    let mut elements: HashMap<String, Vec<String>> = HashMap::new();
+    let mut paths: HashSet<String> = HashSet::new();
+    // Name of variable, which is read now; fish shows a variable once per scope
+    let mut current: Option<String> = None;
+    let mut lines = output.lines();
+    while let Some(line) = lines.next() {
+        let Some((head, rest)) = line.strip_prefix('$').and_then(|l| l.split_once(": ")) else {
+            continue;
+        };
+        if let Some((name, _)) = head.split_once('[') {
+            if current.as_deref() != Some(name) {
+                continue;
+            }
+            let Some(start) = rest.find('|') else {
+                continue;
+            };
+            let mut value = rest[start + 1..].to_owned();
+            // Value can have line breaks
+            while !value.ends_with('|') {
+                let Some(next) = lines.next() else {
+                    break;
+                };
+                value.push('\n');
+                value.push_str(next);
+            }
+            value.pop();
+            elements.entry(name.to_owned()).or_default().push(value);
+        } else if let Some(scope) = rest
+            .strip_prefix("set in ")
+            .and_then(|r| r.split_whitespace().next())
+        {
+            if vars.contains_key(head) {
+                // Variable is shadowed by the scope shown before
+                current = None;
+                continue;
+            }
+            let scope = match scope {
+                "universal" => VarScope::Universal,
+                "local" => VarScope::Local,
+                _ => VarScope::Global,
+            };
+            if rest.contains("path variable") || is_path_list(head) {
+                paths.insert(head.to_owned());
+            }
+            vars.insert(
+                head.to_owned(),
+                ShellVar {
+                    value: EnvValue::default(),
+                    scope,
+                    exported: rest.contains(", exported"),
+                },
+            );
+            current = Some(head.to_owned());
+        }
+    }
+    // Each variable of fish is a list; lists with one element are considered as
+    // scalars, except lists of paths
+    for (name, var) in vars.iter_mut() {
+        let mut elements = elements.remove(name).unwrap_or_default();
+        var.value = if elements.len() == 1 && !paths.contains(name) {
+            EnvValue::Scalar(elements.remove(0))
+        } else {
+            EnvValue::List(elements)
+        };
+    }
+    vars
+}
+
+/// Parses output of `set` (csh, tcsh)
+fn parse_csh(output: &str) -> HashMap<String, ShellVar> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once('\t').unwrap_or((line, ""));
+            if name.is_empty() || name.contains(' ') {
+                return None;
+            }
+            let value = match value.strip_prefix('(').and_then(|v| v.strip_suffix(')')) {
+                Some(list) => {
+                    EnvValue::List(list.split_whitespace().map(|v| v.to_owned()).collect())
+                }
+                None => EnvValue::from(value),
+            };
+            Some((
+                name.to_owned(),
+                ShellVar {
+                    value,
+                    scope: VarScope::Global,
+                    exported: false,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Converts JSON value into value of variable. Values, which aren't strings or
+/// lists of strings, are kept as JSON.
+fn from_json(value: serde_json::Value) -> EnvValue {
+    let text = |value: serde_json::Value| match value {
+        serde_json::Value::String(value) => value,
+        serde_json::Value::Null => String::new(),
+        value => value.to_string(),
+    };
+    match value {
+        serde_json::Value::Array(values) => EnvValue::List(values.into_iter().map(text).collect()),
+        value => EnvValue::Scalar(text(value)),
+    }
+}
+
+/// Parses JSON, which is printed by the shell
+fn json<'a, T: Deserialize<'a>>(output: &'a str) -> Result<T, Error> {
+    serde_json::from_str::<T>(output.trim()).map_err(|source| Error::Parsing {
+        source,
+        diagnostics: Box::new(Diagnostics::default().with_stdout(output)),
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PsVar {
+    name: String,
+    /// Value converted into JSON
+    value: Option<String>,
+    exported: bool,
+}
+
+/// Parses JSON output of `Get-Variable` and `Get-ChildItem env:` (PowerShell).
+/// Environment variables are named like `env:PATH`, because PowerShell has own
+/// variables with the same names (like `HOME`).
+fn parse_powershell(output: &str) -> Result<HashMap<String, ShellVar>, Error> {
+    json::<Vec<PsVar>>(output)?
+        .into_iter()
+        .map(|var| {
+            let value = match var.value {
+                Some(value) => from_json(json(&value)?),
+                None => EnvValue::default(),
+            };
+            Ok((
+                var.name,
+                ShellVar {
+                    value,
+                    scope: VarScope::Global,
+                    exported: var.exported,
+                },
+            ))
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct NuVar {
+    /// Name with `$`
+    name: String,
+    /// Value converted into JSON
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct NuVars {
+    env: HashMap<String, serde_json::Value>,
+    vars: Vec<NuVar>,
+}
+
+/// Parses JSON output of `$env` and `scope variables` (nushell)
+fn parse_nu(output: &str) -> Result<HashMap<String, ShellVar>, Error> {
+    let NuVars { env, vars } = json::<NuVars>(output)?;
+    let mut result: HashMap<String, ShellVar> = HashMap::new();
+    for var in vars {
+        let name = var.name.strip_prefix('$').unwrap_or(&var.name).to_owned();
+        result.insert(
+            name,
+            ShellVar {
+                value: from_json(json(&var.value)?),
+                scope: VarScope::Global,
+                exported: false,
+            },
+        );
+    }
+    for (name, value) in env {
+        result.insert(
+            name,
+            ShellVar {
+                value: from_json(value),
+                scope: VarScope::Global,
+                exported: true,
+            },
+        );
+    }
+    Ok(result)
+}
+
+/// Returns output of the shell after `MARKER`
+pub(crate) fn after_marker(output: &str) -> Result<&str, Error> {
+    match output.find(MARKER) {
+        Some(pos) => Ok(&output[pos + MARKER.len()..]),
+        None => Err(Error::Other(format!(
+            "Fail to find list of variables in output: {}",
+            truncate(output)
+        ))),
+    }
+}
+
+/// Parses the list of variables printed by the shell
+pub(crate) fn parse(kind: ShellKind, output: &str) -> Result<HashMap<String, ShellVar>, Error> {
+    let output = after_marker(output)?;
+    Ok(match kind {
+        ShellKind::Fish => parse_fish(output),
+        ShellKind::Csh => parse_csh(output),
+        ShellKind::PowerShell => parse_powershell(output)?,
+        ShellKind::Nu => parse_nu(output)?,
+        _ => parse_declarations(output),
+    })
+}
+
+/// Runs the shell with the script and returns stdout of the shell. Environment
+/// variables and the working folder are taken from `options`.
+pub(crate) fn run(
+    shell: &PathBuf,
+    args: &[String],
+    script: &str,
+    options: &Options,
+) -> Result<String, Error> {
+    let mut command = Command::new(shell);
+    options
+        .apply(&mut command)
+        .args(args)
+        .arg(script)
+        .stdin(Stdio::null());
+    #[cfg(windows)]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    let started = Instant::now();
+    let output = command.output().map_err(|source| {
+        Error::Executing {
+            source,
+            diagnostics: Box::new(Diagnostics::new(Some(shell), args)),
+        }
+        .with_command(Some(shell), args, started.elapsed())
+    })?;
+    if !output.status.success() {
+        return Err(Error::Executing {
+            source: io::Error::other(format!("shell is finished with {}", output.status)),
+            diagnostics: Box::new(Diagnostics::new(Some(shell), args).with_output(&output)),
+        }
+        .with_command(Some(shell), args, started.elapsed()));
+    }
+    Ok(from_utf8(&output.stdout)
+        .map_err(Error::Decoding)?
+        .to_owned())
+}
+
+/// Runs the shell and asks it to print all own variables. See
+/// `Profile::load_shellvars`.
+pub(crate) fn load(
+    shell: &PathBuf,
+    kind: ShellKind,
+    args: &[String],
+    options: &Options,
+) -> Result<HashMap<String, ShellVar>, Error> {
+    parse(kind, &run(shell, args, &script(kind)?, options)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(values: &[&str]) -> EnvValue {
+        EnvValue::List(values.iter().map(|v| v.to_string()).collect())
+    }
+
+    #[test]
+    fn bash() {
+        let bash = parse(
+            ShellKind::Bash,
+            "noise\nENVVARS|VARS\n\
+             declare -- HISTFILE=\"/root/.bash_history\"\n\
+             declare -x PATH=\"/usr/bin:/bin\"\n\
+             declare -a arr=([0]=\"a b\" [1]=\"c)\" [2]=\"d\")\n\
+             declare -x OLDPWD\n\
+             declare -- MULTI=\"line1\nline2\"\n",
+        )
+        .expect("Variables should be parsed");
+        assert!(!bash["HISTFILE"].exported);
+        assert_eq!(bash["HISTFILE"].value, "/root/.bash_history".into());
+        assert!(bash["PATH"].exported);
+        assert_eq!(bash["arr"].value, list(&["a b", "c)", "d"]));
+        assert_eq!(bash["OLDPWD"].value, "".into());
+        assert_eq!(bash["MULTI"].value, "line1\nline2".into());
+    }
+
+    #[test]
+    fn zsh() {
+        let zsh = parse(
+            ShellKind::Zsh,
+            "ENVVARS|VARS\ntypeset -g -a fpath=( /usr/share/zsh /opt )\n\
+             typeset -T PATH path=( /usr/bin /bin )\nexport LANG=C\n",
+        )
+        .expect("Variables should be parsed");
+        assert_eq!(zsh["fpath"].value, list(&["/usr/share/zsh", "/opt"]));
+        assert_eq!(zsh["PATH"].value, list(&["/usr/bin", "/bin"]));
+        assert!(zsh["LANG"].exported);
+    }
+
+    #[test]
+    fn fish() {
+        let fish = parse(
+            ShellKind::Fish,
+            "ENVVARS|VARS\n\
+             $fish_user_paths: set in universal scope, unexported, with 2 elements\n\
+             $fish_user_paths[1]: |/opt/bin|\n\
+             $fish_user_paths[2]: |/usr/local/bin|\n\
+             $PATH: set in global scope, exported, a path variable with 2 elements\n\
+             $PATH[1]: |/usr/bin|\n\
+             $PATH[2]: |/bin|\n\
+             $PATH: originally inherited as |/usr/bin:/bin|\n",
+        )
+        .expect("Variables should be parsed");
+        assert_eq!(
+            fish["fish_user_paths"].value,
+            list(&["/opt/bin", "/usr/local/bin"])
+        );
+        assert_eq!(fish["fish_user_paths"].scope, VarScope::Universal);
+        assert_eq!(fish["PATH"].value, list(&["/usr/bin", "/bin"]));
+        assert!(fish["PATH"].exported);
+    }
+
+    #[test]
+    fn sh() {
+        let sh = parse(
+            ShellKind::Sh,
+            "ENVVARS|VARS\nHOME='/root'\nPS1='$ '\nexport HOME='/root'\n",
+        )
+        .expect("Variables should be parsed");
+        assert!(sh["HOME"].exported);
+        assert!(!sh["PS1"].exported);
+    }
+
+    #[test]
+    fn csh() {
+        let csh = parse(
+            ShellKind::Csh,
+            "ENVVARS|VARS\npath\t(/usr/bin /bin)\nprompt\t%# \nnoclobber\n",
+        )
+        .expect("Variables should be parsed");
+        assert_eq!(csh["path"].value, list(&["/usr/bin", "/bin"]));
+        assert_eq!(csh["prompt"].value, "%# ".into());
+        assert_eq!(csh["noclobber"].value, "".into());
+    }
+
+    #[test]
+    fn missing_marker() {
+        assert!(matches!(
+            parse(ShellKind::Bash, "declare -x PATH=\"/bin\"\n"),
+            Err(Error::Other(_))
+        ));
+    }
+
+    #[test]
+    fn nu() {
+        let nu = parse(
+            ShellKind::Nu,
+            r#"ENVVARS|VARS {"env":{"PATH":["/usr/bin","/bin"],"SHLVL":1,"HOME":"/root"},"vars":[{"name":"$count","value":"3"},{"name":"$dirs","value":"[\"/a\",\"/b\"]"},{"name":"$hook","value":"null"}]}"#,
+        )
+        .expect("Variables should be parsed");
+        assert_eq!(nu["PATH"].value, list(&["/usr/bin", "/bin"]));
+        assert_eq!(nu["SHLVL"].value, "1".into());
+        assert_eq!(nu["HOME"].value, "/root".into());
+        assert!(nu["HOME"].exported);
+        assert_eq!(nu["count"].value, "3".into());
+        assert_eq!(nu["dirs"].value, list(&["/a", "/b"]));
+        assert_eq!(nu["hook"].value, "".into());
+        assert!(!nu["count"].exported);
+    }
+
+    #[test]
+    fn powershell() {
+        let ps = parse(
+            ShellKind::PowerShell,
+            r#"ENVVARS|VARS
+[{"Name":"PSEdition","Value":"\"Core\"","Exported":false},{"Name":"args","Value":"[\"a\",\"b c\"]","Exported":false},{"Name":"one","Value":"[\"x\"]","Exported":false},{"Name":"HOME","Value":"\"/home/ps\"","Exported":false},{"Name":"env:HOME","Value":"\"/root\"","Exported":true}]"#,
+        )
+        .expect("Variables should be parsed");
+        assert_eq!(ps["PSEdition"].value, "Core".into());
+        assert!(!ps["PSEdition"].exported);
+        assert_eq!(ps["args"].value, list(&["a", "b c"]));
+        assert_eq!(ps["one"].value, list(&["x"]));
+        assert_eq!(ps["HOME"].value, "/home/ps".into());
+        assert_eq!(ps["env:HOME"].value, "/root".into());
+        assert!(ps["env:HOME"].exported);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_shell() {
+        let err = run(
+            &PathBuf::from("/bin/sh"),
+            &["-c".to_owned()],
+            "echo 'ENVVARS|VARS'; echo oops >&2; exit 3",
+            &Options::default(),
+        )
+        .expect_err("Failed shell should be reported");
+        assert_eq!(err.kind(), crate::ErrorKind::ExitFailure);
+        let diagnostics = err.diagnostics().expect("Diagnostics should be added");
+        assert_eq!(diagnostics.code, Some(3));
+        assert_eq!(diagnostics.stderr.trim(), "oops");
+        assert_eq!(diagnostics.shell, Some(PathBuf::from("/bin/sh")));
+        assert_eq!(diagnostics.args, vec!["-c".to_owned()]);
+        assert!(diagnostics.elapsed.is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn closed_stdin() {
+        // Interactive shell cannot wait for input of the user
+        let output = run(
+            &PathBuf::from("/bin/sh"),
+            &["-c".to_owned()],
+            "if read line; then echo input; else echo eof; fi",
+            &Options::default(),
+        )
+        .expect("Shell should be executed");
+        assert_eq!(output.trim(), "eof");
+    }
+}
@@ -0,0 +1,199 @@
+/// Returns true if given string can be used as name of variable
+pub(crate) fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits command line into words considering POSIX-like quoting: single quotes,
+/// double quotes, ANSI-C quotes (`$'...'`) and backslash escaping. Quotes are removed,
+/// no expansion is done.
+pub(crate) fn words(input: &str) -> Vec<String> {
+    let mut words: Vec<String> = vec![];
+    let mut word = String::new();
+    let mut started = false;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                started = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    word.push(c);
+                }
+            }
+            '"' => {
+                started = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.peek() {
+                            Some(&next) if matches!(next, '"' | '\\' | '$' | '`') => {
+                                word.push(next);
+                                chars.next();
+                            }
+                            _ => word.push(c),
+                        },
+                        _ => word.push(c),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                started = true;
+                chars.next();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\'' => break,
+                        '\\' => match chars.next() {
+                            Some('n') => word.push('\n'),
+                            Some('t') => word.push('\t'),
+                            Some('r') => word.push('\r'),
+                            Some('e') | Some('E') => word.push('\x1b'),
+                            Some(next) => word.push(next),
+                            None => word.push(c),
+                        },
+                        _ => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                started = true;
+                if let Some(next) = chars.next() {
+                    if next != '\n' {
+                        word.push(next);
+                    }
+                }
+            }
+            c if c.is_whitespace() => {
+                if started {
+                    words.push(std::mem::take(&mut word));
+                    started = false;
+                }
+            }
+            _ => {
+                started = true;
+                word.push(c);
+            }
+        }
+    }
+    if started {
+        words.push(word);
+    }
+    words
+}
+
+/// Splits line of script into statements (separated by `;`, `&&`, `||` and `|`) and
+/// each statement into words. Unlike `words`, quotes are kept. Comments are removed.
+/// Command substitutions (`$(...)`, backticks) are kept as a part of word. Each
+/// statement goes with a flag, which is true if the statement is chained with `&&`
+/// or `||` and runs depending on the result of previous statements.
+pub(crate) fn statements(line: &str) -> Vec<(Vec<String>, bool)> {
+    let mut statements: Vec<(Vec<String>, bool)> = vec![];
+    let mut statement: Vec<String> = vec![];
+    let mut chained = false;
+    let mut word = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quote: Option<char> = None;
+    let mut nested: usize = 0;
+    while let Some(c) = chars.next() {
+        if let Some(q) = quote {
+            word.push(c);
+            if c == '\\' && q != '\'' {
+                word.extend(chars.next());
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        if nested > 0 {
+            word.push(c);
+            match c {
+                '(' => nested += 1,
+                ')' => nested -= 1,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' | '`' => {
+                quote = Some(c);
+                word.push(c);
+            }
+            '\\' => {
+                word.push(c);
+                word.extend(chars.next());
+            }
+            '$' if chars.peek() == Some(&'(') => {
+                nested += 1;
+                word.push(c);
+                word.extend(chars.next());
+            }
+            '#' if word.is_empty() => break,
+            ';' | '&' | '|' => {
+                let double = c != ';' && chars.peek() == Some(&c);
+                if double {
+                    chars.next();
+                }
+                if !word.is_empty() {
+                    statement.push(std::mem::take(&mut word));
+                }
+                if !statement.is_empty() {
+                    statements.push((std::mem::take(&mut statement), chained));
+                }
+                // Statements of a pipeline keep the flag of the pipeline
+                chained = double || (c == '|' && chained);
+            }
+            c if c.is_whitespace() => {
+                if !word.is_empty() {
+                    statement.push(std::mem::take(&mut word));
+                }
+            }
+            _ => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        statement.push(word);
+    }
+    if !statement.is_empty() {
+        statements.push((statement, chained));
+    }
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(
+            words(r#"export 'BAZ=a b' X="q \"w\"" $'t\tn' e\ s"#),
+            vec!["export", "BAZ=a b", "X=q \"w\"", "t\tn", "e s"]
+        );
+        assert_eq!(words("  "), Vec::<String>::new());
+        assert_eq!(words("A=''"), vec!["A="]);
+        assert!(is_name("_JAVA_HOME1"));
+        assert!(!is_name("1A"));
+        assert!(!is_name("BASH_FUNC_x%%"));
+        assert_eq!(
+            statements(r#"A="x; y" && export A; B=$(echo 1; echo 2) # comment"#),
+            vec![
+                (vec![r#"A="x; y""#.to_owned()], false),
+                (vec!["export".to_owned(), "A".to_owned()], true),
+                (vec!["B=$(echo 1; echo 2)".to_owned()], false)
+            ]
+        );
+        let chained = |line: &str| {
+            statements(line)
+                .into_iter()
+                .map(|(_, chained)| chained)
+                .collect::<Vec<bool>>()
+        };
+        assert_eq!(
+            chained("a || b | c; d & e"),
+            vec![false, true, true, false, false]
+        );
+    }
+}
@@ -0,0 +1,421 @@
+use crate::{merge::current, syntax::is_name, Error};
+use home::home_dir;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Format of a system file with environment variables
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemFormat {
+    /// `/etc/environment`: `KEY=VALUE` lines, optional `export` keyword, quotes
+    /// around value are removed, no expansion
+    Environment,
+    /// `/etc/security/pam_env.conf` and `~/.pam_environment`:
+    /// `VARIABLE [DEFAULT=[value]] [OVERRIDE=[value]]` lines with `${VAR}`, `@{HOME}`
+    /// and `@{SHELL}` expansion. Lines `KEY=VALUE` are accepted too.
+    PamEnv,
+    /// systemd `environment.d/*.conf`: `KEY=VALUE` lines with shell-like quoting and
+    /// `$VAR`, `${VAR}`, `${VAR:-default}`, `${VAR:+alternate}` expansion
+    EnvironmentD,
+}
+
+/// Config folder of the user: `config_home` (the value of `XDG_CONFIG_HOME`) if
+/// it's an absolute path, otherwise `~/.config`
+fn config_dir(config_home: Option<OsString>) -> Option<PathBuf> {
+    config_home
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| home_dir().map(|home| home.join(".config")))
+}
+
+/// Folders of systemd `environment.d`, the folder with the highest priority goes first
+fn environment_d_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = vec![];
+    if let Some(config) = config_dir(env::var_os("XDG_CONFIG_HOME")) {
+        dirs.push(config.join("environment.d"));
+    }
+    dirs.extend(
+        [
+            "/etc/environment.d",
+            "/run/environment.d",
+            "/usr/local/lib/environment.d",
+            "/usr/lib/environment.d",
+        ]
+        .iter()
+        .map(PathBuf::from),
+    );
+    dirs
+}
+
+fn lookup(name: &str, vars: &HashMap<String, String>, base: &HashMap<String, String>) -> String {
+    vars.get(name)
+        .or_else(|| base.get(name))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Removes quotes around whole value, if value is quoted
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return &value[1..value.len() - 1];
+        }
+    }
+    value
+}
+
+/// Expands `${VAR}`, `@{HOME}` and `@{SHELL}` following pam_env rules. `\$` and `\@`
+/// are escaped characters.
+fn pam_expand(
+    value: &str,
+    vars: &HashMap<String, String>,
+    base: &HashMap<String, String>,
+) -> String {
+    let mut result = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if matches!(chars.peek(), Some('$') | Some('@')) => {
+                result.extend(chars.next());
+            }
+            '$' | '@' if chars.peek() == Some(&'{') => {
+                chars.next();
+                let name = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                if c == '$' {
+                    result.push_str(&lookup(&name, vars, base));
+                } else {
+                    match name.as_str() {
+                        "HOME" => result.push_str(
+                            &home_dir()
+                                .map(|h| h.to_string_lossy().to_string())
+                                .unwrap_or_default(),
+                        ),
+                        "SHELL" => result.push_str(&lookup("SHELL", vars, base)),
+                        _ => {}
+                    }
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Expands variables following systemd rules and removes quotes
+fn systemd_expand(
+    value: &str,
+    vars: &HashMap<String, String>,
+    base: &HashMap<String, String>,
+) -> String {
+    let mut result = String::new();
+    let mut chars = value.trim().chars().peekable();
+    let mut quote: Option<char> = None;
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\'', None) | ('"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('\\', q) if q != Some('\'') => result.extend(chars.next()),
+            ('$', q) if q != Some('\'') => {
+                if chars.peek() == Some(&'{') {
+                    chars.next();
+                    let expr = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                    if let Some((name, default)) = expr.split_once(":-") {
+                        let value = lookup(name, vars, base);
+                        result.push_str(if value.is_empty() { default } else { &value });
+                    } else if let Some((name, alternate)) = expr.split_once(":+") {
+                        if !lookup(name, vars, base).is_empty() {
+                            result.push_str(alternate);
+                        }
+                    } else {
+                        result.push_str(&lookup(&expr, vars, base));
+                    }
+                } else {
+                    let mut name = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_ascii_alphanumeric() || c == '_' {
+                            name.push(c);
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    if name.is_empty() {
+                        result.push('$');
+                    } else {
+                        result.push_str(&lookup(&name, vars, base));
+                    }
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Joins lines ending with backslash
+fn logical_lines(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    let mut current = String::new();
+    for line in content.lines() {
+        if let Some(line) = line.strip_suffix('\\') {
+            current.push_str(line);
+            continue;
+        }
+        current.push_str(line);
+        lines.push(std::mem::take(&mut current));
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Splits line by whitespaces, which aren't in double quotes. Quotes are kept.
+fn pam_words(line: &str) -> Vec<&str> {
+    let mut words: Vec<&str> = vec![];
+    let mut start: Option<usize> = None;
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if c.is_whitespace() && !quoted {
+            if let Some(from) = start.take() {
+                words.push(&line[from..i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(from) = start {
+        words.push(&line[from..]);
+    }
+    words
+}
+
+fn parse_pam_env_line(
+    line: &str,
+    vars: &mut HashMap<String, String>,
+    base: &HashMap<String, String>,
+) {
+    let mut words = pam_words(line).into_iter();
+    let Some(name) = words.next() else {
+        return;
+    };
+    if let Some((name, _)) = name.split_once('=') {
+        // Simple "KEY=VALUE" line
+        if is_name(name) {
+            vars.insert(name.to_owned(), unquote(&line[name.len() + 1..]).to_owned());
+        }
+        return;
+    }
+    if !is_name(name) {
+        return;
+    }
+    let mut default: Option<String> = None;
+    let mut overridden: Option<String> = None;
+    for option in words {
+        if let Some(value) = option.strip_prefix("DEFAULT=") {
+            default = Some(pam_expand(unquote(value), vars, base));
+        } else if let Some(value) = option.strip_prefix("OVERRIDE=") {
+            overridden = Some(pam_expand(unquote(value), vars, base));
+        }
+    }
+    let value = overridden
+        .filter(|v| !v.is_empty())
+        .or(default)
+        .unwrap_or_default();
+    if value.is_empty() {
+        vars.remove(name);
+    } else {
+        vars.insert(name.to_owned(), value);
+    }
+}
+
+/// Parses content of a system file with environment variables and puts variables
+/// into `vars`. Variables, which are already in `vars`, are used for expansion and
+/// could be overwritten.
+/// * `format` - format of file
+/// * `content` - content of file
+/// * `vars` - variables defined by previous files
+/// * `base` - variables used for expansion if a variable isn't defined in `vars`
+pub fn parse_system_file(
+    format: SystemFormat,
+    content: &str,
+    vars: &mut HashMap<String, String>,
+    base: &HashMap<String, String>,
+) {
+    for line in logical_lines(content) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match format {
+            SystemFormat::Environment => {
+                let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+                if let Some((name, value)) = line.split_once('=') {
+                    if is_name(name) {
+                        vars.insert(name.to_owned(), unquote(value).to_owned());
+                    }
+                }
+            }
+            SystemFormat::PamEnv => parse_pam_env_line(line, vars, base),
+            SystemFormat::EnvironmentD => {
+                if let Some((name, value)) = line.split_once('=') {
+                    if is_name(name) {
+                        let value = systemd_expand(value, vars, base);
+                        vars.insert(name.to_owned(), value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<Option<String>, Error> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Error::Io(err)),
+    }
+}
+
+/// Returns list of system files with environment variables in order of processing.
+/// The list includes only existing files.
+pub fn get_system_files() -> Result<Vec<(PathBuf, SystemFormat)>, Error> {
+    let mut files: Vec<(PathBuf, SystemFormat)> = vec![
+        (
+            PathBuf::from("/etc/security/pam_env.conf"),
+            SystemFormat::PamEnv,
+        ),
+        (PathBuf::from("/etc/environment"), SystemFormat::Environment),
+    ];
+    if let Some(home) = home_dir() {
+        files.push((home.join(".pam_environment"), SystemFormat::PamEnv));
+    }
+    files.retain(|(path, _)| path.is_file());
+    // Files with the same name from folders with higher priority mask others. Files
+    // are processed in lexicographic order of names.
+    let mut confs: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for dir in environment_d_dirs().iter().rev() {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(Error::Io(err)),
+        };
+        for entry in entries {
+            let path = entry.map_err(Error::Io)?.path();
+            if path.extension().map(|e| e == "conf").unwrap_or(false) {
+                if let Some(name) = path.file_name() {
+                    confs.insert(name.to_string_lossy().to_string(), path);
+                }
+            }
+        }
+    }
+    files.extend(
+        confs
+            .into_values()
+            .map(|path| (path, SystemFormat::EnvironmentD)),
+    );
+    Ok(files)
+}
+
+/// Reads environment variables, which are set by the system without any shell:
+/// `/etc/security/pam_env.conf`, `/etc/environment`, `~/.pam_environment` and
+/// systemd `environment.d/*.conf`. Files are parsed natively, nothing is executed.
+/// If a file refers to a variable, which isn't defined by previous files, the value
+/// is taken from the environment of the current process.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+/// use envvars::get_system_envvars;
+///
+/// let system: HashMap<String, String> = get_system_envvars().unwrap();
+/// for (key, value) in system.iter() {
+///     println!("{key}={value}");
+/// }
+/// ```
+pub fn get_system_envvars() -> Result<HashMap<String, String>, Error> {
+    let base = current();
+    let mut vars: HashMap<String, String> = HashMap::new();
+    for (path, format) in get_system_files()? {
+        if let Some(content) = read(&path)? {
+            parse_system_file(format, &content, &mut vars, &base);
+        }
+    }
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(format: SystemFormat, content: &str) -> HashMap<String, String> {
+        let base = HashMap::from([
+            (String::from("PATH"), String::from("/usr/bin")),
+            (String::from("SHELL"), String::from("/bin/zsh")),
+        ]);
+        let mut vars = HashMap::new();
+        parse_system_file(format, content, &mut vars, &base);
+        vars
+    }
+
+    #[test]
+    fn test() {
+        let vars = parse(
+            SystemFormat::Environment,
+            "# comment\nPATH=\"/usr/local/bin:/usr/bin\"\nexport LANG='en_US.UTF-8'\nA=${B}\n",
+        );
+        assert_eq!(vars.get("PATH").unwrap(), "/usr/local/bin:/usr/bin");
+        assert_eq!(vars.get("LANG").unwrap(), "en_US.UTF-8");
+        assert_eq!(vars.get("A").unwrap(), "${B}");
+        let vars = parse(
+            SystemFormat::PamEnv,
+            "REMOTEHOST DEFAULT=localhost OVERRIDE=@{PAM_RHOST}\n\
+             DISPLAY DEFAULT=${REMOTEHOST}:0.0 OVERRIDE=${DISPLAY}\n\
+             MY_SHELL DEFAULT=@{SHELL}\n\
+             PRICE DEFAULT=\\$10\n\
+             GREETING DEFAULT=\"hello world\"\n\
+             EMPTY DEFAULT=\n\
+             SIMPLE=\"value\"\n",
+        );
+        assert_eq!(vars.get("REMOTEHOST").unwrap(), "localhost");
+        assert_eq!(vars.get("DISPLAY").unwrap(), "localhost:0.0");
+        assert_eq!(vars.get("MY_SHELL").unwrap(), "/bin/zsh");
+        assert_eq!(vars.get("PRICE").unwrap(), "$10");
+        assert_eq!(vars.get("GREETING").unwrap(), "hello world");
+        assert_eq!(vars.get("SIMPLE").unwrap(), "value");
+        assert!(!vars.contains_key("EMPTY"));
+        let vars = parse(
+            SystemFormat::EnvironmentD,
+            "PATH=/opt/bin:$PATH\nA=\"a b\"\nB=${A:+set}\nC=${UNDEFINED:-default}\nD='$A'\n",
+        );
+        assert_eq!(vars.get("PATH").unwrap(), "/opt/bin:/usr/bin");
+        assert_eq!(vars.get("A").unwrap(), "a b");
+        assert_eq!(vars.get("B").unwrap(), "set");
+        assert_eq!(vars.get("C").unwrap(), "default");
+        assert_eq!(vars.get("D").unwrap(), "$A");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn config_home() {
+        assert_eq!(
+            config_dir(Some(OsString::from("/xdg/config"))),
+            Some(PathBuf::from("/xdg/config"))
+        );
+        // Empty and relative paths are ignored (see XDG Base Directory Specification)
+        let fallback = home_dir().map(|home| home.join(".config"));
+        assert_eq!(config_dir(Some(OsString::new())), fallback);
+        assert_eq!(config_dir(Some(OsString::from("config"))), fallback);
+        assert_eq!(config_dir(None), fallback);
+    }
+}
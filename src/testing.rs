@@ -0,0 +1,41 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Counter of temporary folders, which makes names unique in the process
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Temporary folder of a test. The folder is created on creation and removed on
+/// drop, also if the test panics. Name of the folder has pid, time and a counter,
+/// so tests of different test binaries (and parallel tests) don't share folders.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        let path = env::temp_dir().join(format!(
+            "envvars-test-{name}-{}-{nanos}-{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path).expect("Temporary folder should be created");
+        TempDir(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
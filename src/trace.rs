@@ -0,0 +1,328 @@
+use crate::{
+    merge::is_path_list,
+    syntax::{is_name, words},
+    Error, ShellKind,
+};
+use std::{
+    collections::HashMap,
+    env,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Marker, which is used in trace prompt to distinguish trace records from any
+/// other output of the shell
+const MARKER: &str = "ENVVARS|";
+
+/// Arguments and environment variables, which should be added to the shell's command
+/// to enable tracing
+pub(crate) struct Tracing {
+    pub args: Vec<String>,
+    pub envs: Vec<(String, String)>,
+    /// File, which the shell writes a report of timings into (fish only)
+    pub report: Option<PathBuf>,
+}
+
+impl Tracing {
+    /// Removes variables, which were added to enable tracing, from variables reported
+    /// by the shell. If such variable is inherited (from the current process or from
+    /// `inherited`), the inherited value is restored.
+    pub fn restore(&self, envvars: &mut HashMap<String, String>, inherited: &[(String, String)]) {
+        for (key, _) in self.envs.iter() {
+            let value = inherited
+                .iter()
+                .rev()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.clone())
+                .or_else(|| env::var(key).ok());
+            match value {
+                Some(value) => {
+                    envvars.insert(key.clone(), value);
+                }
+                None => {
+                    envvars.remove(key);
+                }
+            }
+        }
+    }
+}
+
+/// Single traced command
+#[derive(Debug, Clone)]
+pub(crate) struct Record {
+    /// Time (seconds since UNIX epoch) when command has been started, if shell
+    /// reports it
+    pub time: Option<f64>,
+    /// Startup file, which has the command, if shell reports it
+    pub file: Option<PathBuf>,
+    /// Line in startup file, if shell reports it
+    pub line: Option<usize>,
+    /// Traced command
+    pub command: String,
+}
+
+/// Assignment of variable found in trace
+#[derive(Debug, Clone)]
+pub(crate) struct Assignment {
+    pub name: String,
+    /// Assigned value; `None` if variable is only exported
+    pub value: Option<String>,
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+}
+
+/// Returns arguments and variables to enable tracing of shell's initialization.
+/// * `timing` - fish doesn't report timestamps in trace, with `timing = true` fish
+///   will be asked to write a report of timings instead of tracing.
+///
+/// Note, bash ignores `PS4` from environment if it's running as root. In this case
+/// trace records don't have time, file and line.
+pub(crate) fn setup(kind: ShellKind, timing: bool) -> Result<Tracing, Error> {
+    let mut report: Option<PathBuf> = None;
+    let (args, envs): (Vec<String>, Vec<(&str, &str)>) = match kind {
+        ShellKind::Bash => (
+            vec![String::from("-x")],
+            vec![
+                (
+                    "PS4",
+                    "+ENVVARS|${EPOCHREALTIME}|${BASH_SOURCE}|${LINENO}| ",
+                ),
+                ("BASH_XTRACEFD", "2"),
+            ],
+        ),
+        ShellKind::Zsh => (
+            vec![String::from("-x")],
+            vec![("PS4", "+ENVVARS|%D{%s.%6.}|%x|%I| ")],
+        ),
+        ShellKind::Sh | ShellKind::Ksh => (
+            vec![String::from("-x")],
+            vec![("PS4", "+ENVVARS|||${LINENO}| ")],
+        ),
+        ShellKind::Fish if timing => {
+            let file = env::temp_dir().join(format!(
+                "envvars-fish-profile-{}-{}.txt",
+                std::process::id(),
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_nanos())
+                    .unwrap_or_default()
+            ));
+            let arg = format!("--profile-startup={}", file.to_string_lossy());
+            report = Some(file);
+            (vec![arg], vec![])
+        }
+        ShellKind::Fish => (vec![], vec![("fish_trace", "1")]),
+        _ => return Err(Error::NotSupportedShell(format!("{kind:?}"))),
+    };
+    Ok(Tracing {
+        args,
+        envs: envs
+            .into_iter()
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+            .collect(),
+        report,
+    })
+}
+
+fn parse_line(kind: ShellKind, line: &str) -> Option<Record> {
+    if kind == ShellKind::Fish {
+        return line
+            .trim_start_matches('-')
+            .strip_prefix("> ")
+            .map(|command| Record {
+                time: None,
+                file: None,
+                line: None,
+                command: command.to_owned(),
+            });
+    }
+    let traced = line.trim_start_matches('+');
+    if traced.len() == line.len() {
+        return None;
+    }
+    let Some(traced) = traced.strip_prefix(MARKER) else {
+        // Prompt isn't applied (bash as root), only command is available
+        return traced.strip_prefix(' ').map(|command| Record {
+            time: None,
+            file: None,
+            line: None,
+            command: command.to_owned(),
+        });
+    };
+    let mut parts = traced.splitn(4, '|');
+    let time = parts.next()?;
+    let file = parts.next()?;
+    let line = parts.next()?;
+    let command = parts.next()?.strip_prefix(' ')?;
+    Some(Record {
+        // Decimal separator of EPOCHREALTIME depends on locale
+        time: time.replace(',', ".").parse::<f64>().ok(),
+        file: if file.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(file))
+        },
+        line: line.parse::<usize>().ok(),
+        command: command.to_owned(),
+    })
+}
+
+/// Parses trace records from stderr of the shell
+pub(crate) fn parse(kind: ShellKind, stderr: &str) -> Vec<Record> {
+    stderr
+        .lines()
+        .filter_map(|line| parse_line(kind, line))
+        .collect()
+}
+
+/// Parses report of timings written by fish with `--profile-startup`. Report has
+/// columns: own time of command (microseconds), time including nested commands and
+/// command. Because fish doesn't report timestamps, time of each record is calculated
+/// as a sum of own times of previous commands.
+pub(crate) fn parse_fish_report(started: f64, report: &str) -> Vec<Record> {
+    let mut time = started;
+    let mut records: Vec<Record> = vec![];
+    for line in report.lines() {
+        let mut columns = line.splitn(3, '\t');
+        let (Some(own), Some(_), Some(command)) = (columns.next(), columns.next(), columns.next())
+        else {
+            continue;
+        };
+        let Ok(own) = own.trim().parse::<u64>() else {
+            continue;
+        };
+        let Some(command) = command.trim_start_matches('-').strip_prefix("> ") else {
+            continue;
+        };
+        records.push(Record {
+            time: Some(time),
+            file: None,
+            line: None,
+            command: command.to_owned(),
+        });
+        time += own as f64 / 1_000_000.0;
+    }
+    records
+}
+
+fn fish_assignments(words: &[String]) -> Vec<(String, Option<String>)> {
+    let mut args = words.iter().skip(1).skip_while(|w| w.starts_with('-'));
+    if words
+        .iter()
+        .skip(1)
+        .take_while(|w| w.starts_with('-'))
+        .any(|flag| {
+            matches!(
+                flag.as_str(),
+                "-e" | "--erase" | "-q" | "--query" | "-n" | "--names" | "-S" | "--show"
+            )
+        })
+    {
+        return vec![];
+    }
+    let Some(name) = args.next() else {
+        return vec![];
+    };
+    let separator = if is_path_list(name) { ":" } else { " " };
+    let value = args.cloned().collect::<Vec<String>>().join(separator);
+    vec![(name.clone(), Some(value))]
+}
+
+fn posix_assignments(words: &[String]) -> Vec<(String, Option<String>)> {
+    let split = |word: &String| -> Option<(String, Option<String>)> {
+        match word.split_once('=') {
+            Some((name, value)) if is_name(name) => Some((name.to_owned(), Some(value.to_owned()))),
+            None if is_name(word) => Some((word.to_owned(), None)),
+            _ => None,
+        }
+    };
+    match words.first().map(|w| w.as_str()) {
+        Some("export") | Some("declare") | Some("typeset") => words
+            .iter()
+            .skip(1)
+            .filter(|w| !w.starts_with('-'))
+            .filter_map(split)
+            .collect(),
+        Some(_) => {
+            // Plain assignments like "A=1 B=2". If there is a command after
+            // assignments, variables are set only for the command.
+            let assignments = words
+                .iter()
+                .map(split)
+                .collect::<Option<Vec<(String, Option<String>)>>>()
+                .unwrap_or_default();
+            if assignments.iter().any(|(_, value)| value.is_none()) {
+                vec![]
+            } else {
+                assignments
+            }
+        }
+        None => vec![],
+    }
+}
+
+/// Extracts assignments of variables from trace records
+pub(crate) fn assignments(kind: ShellKind, records: &[Record]) -> Vec<Assignment> {
+    let mut assignments: Vec<Assignment> = vec![];
+    for record in records.iter() {
+        let words = words(&record.command);
+        let found = if kind == ShellKind::Fish {
+            if words.first().map(|w| w.as_str()) == Some("set") {
+                fish_assignments(&words)
+            } else {
+                vec![]
+            }
+        } else {
+            posix_assignments(&words)
+        };
+        assignments.extend(found.into_iter().map(|(name, value)| Assignment {
+            name,
+            value,
+            file: record.file.clone(),
+            line: record.line,
+        }));
+    }
+    assignments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let stderr = [
+            "bash: no job control in this shell",
+            "+ENVVARS|1.5|/home/user/.bashrc|1| export FOO=bar",
+            "+ENVVARS|1.6|/home/user/.bashrc|4| source /home/user/inc",
+            "++ENVVARS|1.7|/home/user/inc|2| export 'BAZ=a b'",
+            "++ENVVARS|1.8|/home/user/inc|3| JAVA_HOME=/j",
+            "++ENVVARS|1,9|/home/user/inc|3| export JAVA_HOME",
+            "++ENVVARS|2.0|/home/user/inc|4| LANG=C ls",
+            "+ENVVARS|2.1||1| /tmp/extractor",
+        ]
+        .join("\n");
+        let records = parse(ShellKind::Bash, &stderr);
+        assert_eq!(records.len(), 7);
+        assert_eq!(records[6].file, None);
+        assert_eq!(records[4].time, Some(1.9));
+        let found = assignments(ShellKind::Bash, &records);
+        let found = found
+            .iter()
+            .map(|a| (a.name.as_str(), a.value.as_deref(), a.line))
+            .collect::<Vec<(&str, Option<&str>, Option<usize>)>>();
+        assert_eq!(
+            found,
+            vec![
+                ("FOO", Some("bar"), Some(1)),
+                ("BAZ", Some("a b"), Some(2)),
+                ("JAVA_HOME", Some("/j"), Some(3)),
+                ("JAVA_HOME", None, Some(3)),
+            ]
+        );
+        let records = parse(ShellKind::Fish, "> set -gx PATH /a /b\n-> set -q X\n");
+        let found = assignments(ShellKind::Fish, &records);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].value.as_deref(), Some("/a:/b"));
+    }
+}
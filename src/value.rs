@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Separator of lists in environment variables on the current platform
+#[cfg(windows)]
+pub const LIST_SEPARATOR: char = ';';
+/// Separator of lists in environment variables on the current platform
+#[cfg(not(windows))]
+pub const LIST_SEPARATOR: char = ':';
+
+/// Value of variable. Some shells have list-typed variables: all variables of
+/// fish are lists, zsh ties arrays with scalars (`path` and `PATH`), nushell keeps
+/// `$env.PATH` as a list. Such variables are kept as `EnvValue::List`.
+///
+/// Serialized as a string or as an array of strings.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum EnvValue {
+    /// Plain string value
+    Scalar(String),
+    /// List of values
+    List(Vec<String>),
+}
+
+impl EnvValue {
+    /// Creates a list from a string, where elements are separated with the platform
+    /// separator (`:` on unix, `;` on windows), like `PATH`. An empty string gives
+    /// an empty list.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use envvars::{EnvValue, LIST_SEPARATOR};
+    ///
+    /// let value = EnvValue::split(&format!("/usr/bin{LIST_SEPARATOR}/bin"));
+    ///
+    /// assert_eq!(value, EnvValue::List(vec!["/usr/bin".into(), "/bin".into()]));
+    /// assert_eq!(value.join(), format!("/usr/bin{LIST_SEPARATOR}/bin"));
+    /// ```
+    pub fn split(value: &str) -> Self {
+        if value.is_empty() {
+            return EnvValue::List(vec![]);
+        }
+        EnvValue::List(value.split(LIST_SEPARATOR).map(|s| s.to_owned()).collect())
+    }
+
+    /// Returns the value as a string, which can be used as a value of environment
+    /// variable. Elements of a list are joined with the platform separator (`:` on
+    /// unix, `;` on windows).
+    pub fn join(&self) -> String {
+        match self {
+            EnvValue::Scalar(value) => value.clone(),
+            EnvValue::List(values) => values.join(&LIST_SEPARATOR.to_string()),
+        }
+    }
+
+    /// Returns elements of the list. A scalar gives a list with one element.
+    pub fn to_list(&self) -> Vec<String> {
+        match self {
+            EnvValue::Scalar(value) => vec![value.clone()],
+            EnvValue::List(values) => values.clone(),
+        }
+    }
+
+    /// Returns true if the value is a list
+    pub fn is_list(&self) -> bool {
+        matches!(self, EnvValue::List(_))
+    }
+}
+
+impl Default for EnvValue {
+    fn default() -> Self {
+        EnvValue::Scalar(String::new())
+    }
+}
+
+impl fmt::Display for EnvValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.join())
+    }
+}
+
+impl From<String> for EnvValue {
+    fn from(value: String) -> Self {
+        EnvValue::Scalar(value)
+    }
+}
+
+impl From<&str> for EnvValue {
+    fn from(value: &str) -> Self {
+        EnvValue::Scalar(value.to_owned())
+    }
+}
+
+impl From<Vec<String>> for EnvValue {
+    fn from(values: Vec<String>) -> Self {
+        EnvValue::List(values)
+    }
+}
+
+impl From<EnvValue> for String {
+    fn from(value: EnvValue) -> Self {
+        value.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_and_join() {
+        let joined = format!("/usr/bin{LIST_SEPARATOR}{LIST_SEPARATOR}/bin");
+        let value = EnvValue::split(&joined);
+        // Empty elements are kept to restore the same string
+        assert_eq!(
+            value,
+            EnvValue::List(vec!["/usr/bin".into(), "".into(), "/bin".into()])
+        );
+        assert_eq!(value.join(), joined);
+        assert_eq!(EnvValue::split(""), EnvValue::List(vec![]));
+        assert_eq!(EnvValue::List(vec![]).join(), "");
+    }
+
+    #[test]
+    fn round_trip() {
+        let lists: [&[&str]; 5] = [
+            &["/usr/bin", "/bin"],
+            &["/usr/bin"],
+            &["", "/bin"],
+            &["/usr/bin", ""],
+            &["", ""],
+        ];
+        for list in lists {
+            let value = EnvValue::List(list.iter().map(|v| v.to_string()).collect());
+            let joined = value.join();
+            assert_eq!(joined, list.join(&LIST_SEPARATOR.to_string()));
+            assert_eq!(EnvValue::split(&joined), value, "{joined:?}");
+        }
+        // A single element without separator is a list with one element
+        assert_eq!(
+            EnvValue::split("/bin"),
+            EnvValue::List(vec![String::from("/bin")])
+        );
+    }
+
+    #[test]
+    fn scalar() {
+        let value = EnvValue::from("a b");
+        assert!(!value.is_list());
+        assert_eq!(value.to_list(), vec![String::from("a b")]);
+        assert_eq!(value.to_string(), "a b");
+        assert_eq!(EnvValue::default(), EnvValue::from(""));
+    }
+
+    #[test]
+    fn serialization() {
+        let list = EnvValue::from(vec![String::from("a"), String::from("b")]);
+        assert_eq!(
+            serde_json::to_string(&list).expect("Value should be serialized"),
+            r#"["a","b"]"#
+        );
+        assert_eq!(
+            serde_json::from_str::<EnvValue>(r#""a""#).expect("Value should be parsed"),
+            EnvValue::from("a")
+        );
+        assert_eq!(
+            serde_json::from_str::<EnvValue>(r#"["a","b"]"#).expect("Value should be parsed"),
+            list
+        );
+    }
+}